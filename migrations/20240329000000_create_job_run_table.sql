@@ -0,0 +1,15 @@
+CREATE SCHEMA IF NOT EXISTS everytrack_cron;
+
+CREATE TABLE IF NOT EXISTS everytrack_cron.job_run (
+  id UUID PRIMARY KEY,
+  job_name TEXT NOT NULL,
+  status TEXT NOT NULL,
+  error TEXT,
+  items_processed BIGINT,
+  duration_ms BIGINT,
+  scheduled_at TIMESTAMPTZ NOT NULL,
+  started_at TIMESTAMPTZ NOT NULL,
+  finished_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS job_run_job_name_started_at_idx ON everytrack_cron.job_run (job_name, started_at DESC);
@@ -0,0 +1 @@
+ALTER TABLE everytrack_cron.job_run ADD COLUMN IF NOT EXISTS attempts INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS everytrack_cron.exchange_rate_backfill (
+  id UUID PRIMARY KEY,
+  start_date DATE NOT NULL,
+  end_date DATE NOT NULL,
+  status TEXT NOT NULL,
+  error TEXT,
+  total_days INTEGER NOT NULL,
+  completed_days INTEGER NOT NULL DEFAULT 0,
+  skipped_days INTEGER NOT NULL DEFAULT 0,
+  last_completed_date DATE,
+  created_at TIMESTAMPTZ NOT NULL,
+  updated_at TIMESTAMPTZ NOT NULL
+);
@@ -0,0 +1,8 @@
+-- Provider that served the latest exchange rate of each currency pair in everytrack_backend.exchange_rate
+CREATE TABLE IF NOT EXISTS everytrack_cron.exchange_rate_source (
+  base_currency_id UUID NOT NULL,
+  target_currency_id UUID NOT NULL,
+  provider TEXT NOT NULL,
+  updated_at TIMESTAMPTZ NOT NULL,
+  PRIMARY KEY (base_currency_id, target_currency_id)
+);
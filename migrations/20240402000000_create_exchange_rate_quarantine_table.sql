@@ -0,0 +1,18 @@
+-- Exchange rates failing sanity checks are held here instead of being written into everytrack_backend.exchange_rate
+CREATE TABLE IF NOT EXISTS everytrack_cron.exchange_rate_quarantine (
+  id UUID PRIMARY KEY,
+  base_currency_id UUID NOT NULL,
+  target_currency_id UUID NOT NULL,
+  rate TEXT NOT NULL,
+  previous_rate TEXT,
+  provider TEXT NOT NULL,
+  reason TEXT NOT NULL,
+  status TEXT NOT NULL,
+  created_at TIMESTAMPTZ NOT NULL,
+  updated_at TIMESTAMPTZ NOT NULL
+);
+
+-- Keep only the latest suspicious rate of each currency pair waiting for approval
+CREATE UNIQUE INDEX IF NOT EXISTS exchange_rate_quarantine_pending_pair_idx
+ON everytrack_cron.exchange_rate_quarantine (base_currency_id, target_currency_id)
+WHERE status = 'pending';
@@ -0,0 +1,9 @@
+-- Stocks whose quote currency cannot be reconciled with their currency, so their prices are not updated
+CREATE TABLE IF NOT EXISTS everytrack_cron.stock_price_mismatch (
+  stock_id UUID PRIMARY KEY,
+  ticker TEXT NOT NULL,
+  quote_currency TEXT NOT NULL,
+  stock_currency TEXT NOT NULL,
+  quote_price TEXT NOT NULL,
+  detected_at TIMESTAMPTZ NOT NULL
+);
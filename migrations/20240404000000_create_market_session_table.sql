@@ -0,0 +1,6 @@
+-- Latest trading session of each market whose closing prices have been recorded
+CREATE TABLE IF NOT EXISTS everytrack_cron.market_session (
+  country_code TEXT PRIMARY KEY,
+  last_closed_session DATE NOT NULL,
+  updated_at TIMESTAMPTZ NOT NULL
+);
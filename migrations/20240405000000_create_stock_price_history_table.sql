@@ -0,0 +1,13 @@
+-- Daily OHLCV bars of stocks in the currency of the stock, dated in the local timezone of their exchange
+CREATE TABLE IF NOT EXISTS everytrack_cron.stock_price_history (
+  stock_id UUID NOT NULL,
+  date DATE NOT NULL,
+  open NUMERIC NOT NULL,
+  high NUMERIC NOT NULL,
+  low NUMERIC NOT NULL,
+  close NUMERIC NOT NULL,
+  adjusted_close NUMERIC NOT NULL,
+  volume BIGINT NOT NULL,
+  updated_at TIMESTAMPTZ NOT NULL,
+  PRIMARY KEY (stock_id, date)
+);
@@ -0,0 +1,3 @@
+-- Items which failed or were skipped without failing the whole job run, as 'item: reason'
+ALTER TABLE everytrack_cron.job_run ADD COLUMN IF NOT EXISTS failed_items TEXT[] NOT NULL DEFAULT '{}';
+ALTER TABLE everytrack_cron.job_run ADD COLUMN IF NOT EXISTS skipped_items TEXT[] NOT NULL DEFAULT '{}';
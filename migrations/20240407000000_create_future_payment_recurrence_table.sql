@@ -0,0 +1,8 @@
+-- First occurrence of the schedule of each rolling future payment, which the following occurrences are counted from
+-- so that they keep its day of month after being clamped to the end of a shorter month
+CREATE TABLE IF NOT EXISTS everytrack_cron.future_payment_recurrence (
+  future_payment_id UUID PRIMARY KEY,
+  anchored_at TIMESTAMPTZ NOT NULL,
+  frequency BIGINT NOT NULL,
+  updated_at TIMESTAMPTZ NOT NULL
+);
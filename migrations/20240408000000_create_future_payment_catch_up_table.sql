@@ -0,0 +1,13 @@
+-- Future payments which had more than one overdue occurrence settled in one execution, e.g. after the service was down
+CREATE TABLE IF NOT EXISTS everytrack_cron.future_payment_catch_up (
+  id UUID PRIMARY KEY,
+  future_payment_id UUID NOT NULL,
+  occurrences INTEGER NOT NULL,
+  first_occurrence_at TIMESTAMPTZ NOT NULL,
+  last_occurrence_at TIMESTAMPTZ NOT NULL,
+  -- More overdue occurrences are left to the following executions as the cap was reached
+  capped BOOLEAN NOT NULL,
+  caught_up_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS future_payment_catch_up_future_payment_id_idx ON everytrack_cron.future_payment_catch_up (future_payment_id, caught_up_at DESC);
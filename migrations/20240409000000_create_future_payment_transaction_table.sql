@@ -0,0 +1,9 @@
+-- Transaction created for each settled occurrence of a future payment
+-- The future payment and the date of its occurrence are the idempotency key, so that an occurrence is never settled twice
+CREATE TABLE IF NOT EXISTS everytrack_cron.future_payment_transaction (
+  future_payment_id UUID NOT NULL,
+  occurrence_date DATE NOT NULL,
+  transaction_id UUID NOT NULL UNIQUE,
+  created_at TIMESTAMPTZ NOT NULL,
+  PRIMARY KEY (future_payment_id, occurrence_date)
+);
@@ -0,0 +1,2 @@
+-- Service instance executing the job run, so that runs left running by an instance which is gone can be told apart
+ALTER TABLE everytrack_cron.job_run ADD COLUMN IF NOT EXISTS instance_id UUID;
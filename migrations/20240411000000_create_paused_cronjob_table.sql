@@ -0,0 +1,5 @@
+-- Cronjobs paused through the admin API, whose scheduled ticks are skipped by every instance until they are resumed
+CREATE TABLE IF NOT EXISTS everytrack_cron.paused_cronjob (
+  job_name TEXT PRIMARY KEY,
+  paused_at TIMESTAMPTZ NOT NULL
+);
@@ -0,0 +1,2 @@
+-- Ticks skipped while the market is closed are no longer recorded, drop those recorded before
+DELETE FROM everytrack_cron.job_run WHERE status = 'skipped' AND error LIKE 'market % is closed';
@@ -0,0 +1,4 @@
+-- Items upserted by the job run by their effect, only for cronjobs writing their items idempotently, e.g. exchange rate snapshots
+ALTER TABLE everytrack_cron.job_run ADD COLUMN IF NOT EXISTS inserted_items BIGINT;
+ALTER TABLE everytrack_cron.job_run ADD COLUMN IF NOT EXISTS updated_items BIGINT;
+ALTER TABLE everytrack_cron.job_run ADD COLUMN IF NOT EXISTS unchanged_items BIGINT;
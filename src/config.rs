@@ -1,4 +1,11 @@
-use dotenvy::dotenv;
+use crate::cron::retry::RetryPolicy;
+use crate::cron::OverlapPolicy;
+use cron::Schedule;
+use dotenvy::{dotenv, var};
+use rust_decimal::Decimal;
+use std::env;
+use std::str::FromStr;
+use std::time::Duration;
 use time::format_description::well_known::Rfc3339;
 use time::OffsetDateTime;
 
@@ -17,3 +24,386 @@ pub fn load_env_vars() {
     .unwrap()
   );
 }
+
+#[derive(Debug, Clone)]
+pub struct CronjobConfig {
+  pub schedule: String,
+  pub enabled: bool,
+  pub timeout: Option<Duration>,
+  pub overlap_policy: OverlapPolicy,
+  pub retry_policy: RetryPolicy,
+  // Number of most recent days checked for missing records on every run, only for cronjobs catching up on them
+  pub catch_up_days: Option<usize>,
+}
+
+static CRONJOB_CONFIG_FIELDS: [&str; 9] = [
+  "SCHEDULE",
+  "ENABLED",
+  "TIMEOUT_SECONDS",
+  "OVERLAP_POLICY",
+  "RETRY_MAX_ATTEMPTS",
+  "RETRY_BASE_DELAY_SECONDS",
+  "RETRY_MAX_DELAY_SECONDS",
+  "RETRY_JITTER",
+  "CATCH_UP_DAYS",
+];
+
+// Reject any environment variable 'CRONJOB_*' that refers to an unknown cronjob or config field
+pub fn check_unknown_cronjob_configs(names: &[&str]) -> Result<(), String> {
+  check_unknown_cronjob_config_vars(names, &env::vars().collect::<Vec<(String, String)>>())
+}
+
+fn check_unknown_cronjob_config_vars(names: &[&str], vars: &[(String, String)]) -> Result<(), String> {
+  for (key, _) in vars {
+    let Some(cronjob_and_field) = key.strip_prefix("CRONJOB_") else {
+      continue;
+    };
+    let field = CRONJOB_CONFIG_FIELDS
+      .iter()
+      .find(|f| cronjob_and_field.ends_with(&format!("_{f}")))
+      .ok_or_else(|| {
+        format!(
+          "unknown cronjob config field in environment variable {key}. expected one of {}",
+          CRONJOB_CONFIG_FIELDS.join(", ")
+        )
+      })?;
+    let name = cronjob_and_field[..cronjob_and_field.len() - field.len() - 1].to_lowercase();
+    if !names.contains(&name.as_str()) {
+      return Err(format!(
+        "unknown cronjob {name} in environment variable {key}. expected one of {}",
+        names.join(", ")
+      ));
+    }
+  }
+
+  Ok(())
+}
+
+// Load config of cronjob from environment variables 'CRONJOB_<CRONJOB_NAME>_<FIELD>' on top of its defaults
+// e.g. CRONJOB_UPDATE_LATEST_EXCHANGE_RATES_SCHEDULE="0 */5 * * * * *"
+pub fn load_cronjob_config(name: &str, default: CronjobConfig) -> Result<CronjobConfig, String> {
+  parse_cronjob_config(name, default, &env::vars().collect::<Vec<(String, String)>>())
+}
+
+fn parse_cronjob_config(name: &str, default: CronjobConfig, vars: &[(String, String)]) -> Result<CronjobConfig, String> {
+  let prefix = format!("CRONJOB_{}", name.to_uppercase());
+  let var = |field: &str| {
+    let key = format!("{prefix}_{field}");
+    vars.iter().find(|(k, _)| *k == key).map(|(_, value)| value.as_str())
+  };
+  let mut config = default;
+  if let Some(schedule) = var("SCHEDULE") {
+    config.schedule = schedule.to_string();
+  }
+  if let Some(enabled) = var("ENABLED") {
+    config.enabled = enabled
+      .parse::<bool>()
+      .map_err(|e| format!("invalid config for environment variable {prefix}_ENABLED. {}", e))?;
+  }
+  if let Some(timeout_seconds) = var("TIMEOUT_SECONDS") {
+    // Zero timeout means the cronjob can run for as long as it takes
+    let timeout_seconds = timeout_seconds
+      .parse::<u64>()
+      .map_err(|e| format!("invalid config for environment variable {prefix}_TIMEOUT_SECONDS. {}", e))?;
+    config.timeout = (timeout_seconds > 0).then(|| Duration::from_secs(timeout_seconds));
+  }
+  if let Some(overlap_policy) = var("OVERLAP_POLICY") {
+    config.overlap_policy = overlap_policy
+      .parse::<OverlapPolicy>()
+      .map_err(|e| format!("invalid config for environment variable {prefix}_OVERLAP_POLICY. {}", e))?;
+  }
+  if let Some(max_attempts) = var("RETRY_MAX_ATTEMPTS") {
+    config.retry_policy.max_attempts = max_attempts
+      .parse::<u32>()
+      .ok()
+      .filter(|a| *a >= 1)
+      .ok_or_else(|| format!("invalid config for environment variable {prefix}_RETRY_MAX_ATTEMPTS. expected integer at least 1"))?;
+  }
+  if let Some(base_delay_seconds) = var("RETRY_BASE_DELAY_SECONDS") {
+    config.retry_policy.base_delay = Duration::from_secs(
+      base_delay_seconds
+        .parse::<u64>()
+        .map_err(|e| format!("invalid config for environment variable {prefix}_RETRY_BASE_DELAY_SECONDS. {}", e))?,
+    );
+  }
+  if let Some(max_delay_seconds) = var("RETRY_MAX_DELAY_SECONDS") {
+    config.retry_policy.max_delay = Duration::from_secs(
+      max_delay_seconds
+        .parse::<u64>()
+        .map_err(|e| format!("invalid config for environment variable {prefix}_RETRY_MAX_DELAY_SECONDS. {}", e))?,
+    );
+  }
+  if let Some(jitter) = var("RETRY_JITTER") {
+    config.retry_policy.jitter = jitter
+      .parse::<f64>()
+      .ok()
+      .filter(|j| (0.0..=1.0).contains(j))
+      .ok_or_else(|| format!("invalid config for environment variable {prefix}_RETRY_JITTER. expected number between 0 and 1"))?;
+  }
+  if let Some(catch_up_days) = var("CATCH_UP_DAYS") {
+    if config.catch_up_days.is_none() {
+      return Err(format!(
+        "invalid config for environment variable {prefix}_CATCH_UP_DAYS. cronjob {name} does not catch up on missing records"
+      ));
+    }
+    config.catch_up_days = Some(
+      catch_up_days
+        .parse::<usize>()
+        .ok()
+        .filter(|d| *d >= 1)
+        .ok_or_else(|| format!("invalid config for environment variable {prefix}_CATCH_UP_DAYS. expected integer at least 1"))?,
+    );
+  }
+  // Validate the cron expression so that a typo fails the start up instead of silently never running
+  Schedule::from_str(&config.schedule).map_err(|e| format!("invalid cron expression {} for cronjob {name}. {}", config.schedule, e))?;
+
+  Ok(config)
+}
+
+// Shortest admin api key accepted, so that the admin endpoints cannot be left open by an empty or guessable key
+const MIN_ADMIN_API_KEY_LENGTH: usize = 32;
+
+// Load the key protecting the admin endpoints from environment variable 'ADMIN_API_KEY'
+pub fn load_admin_api_key() -> Result<String, String> {
+  let admin_api_key = var("ADMIN_API_KEY").map_err(|e| format!("Missing config for environment variable ADMIN_API_KEY. {}", e))?;
+  if admin_api_key.trim().len() < MIN_ADMIN_API_KEY_LENGTH {
+    return Err(format!(
+      "invalid config for environment variable ADMIN_API_KEY. expected at least {MIN_ADMIN_API_KEY_LENGTH} characters"
+    ));
+  }
+
+  Ok(admin_api_key)
+}
+
+// Load how long to wait for running cronjob executions to finish on shutdown from environment variable 'SHUTDOWN_GRACE_PERIOD_SECONDS'
+// It should be shorter than the time given by the platform between termination signal and forced kill
+pub fn load_shutdown_grace_period() -> Result<Duration, String> {
+  match var("SHUTDOWN_GRACE_PERIOD_SECONDS") {
+    Ok(seconds) => seconds.parse::<u64>().map(Duration::from_secs).map_err(|e| {
+      format!(
+        "invalid value {seconds} for environment variable SHUTDOWN_GRACE_PERIOD_SECONDS. {}",
+        e
+      )
+    }),
+    Err(_) => Ok(Duration::from_secs(50)),
+  }
+}
+
+// Load how long to wait between days of exchange rate backfill from environment variable 'EXCHANGE_RATE_BACKFILL_REQUEST_INTERVAL_MILLISECONDS'
+pub fn load_exchange_rate_backfill_request_interval() -> Result<Duration, String> {
+  match var("EXCHANGE_RATE_BACKFILL_REQUEST_INTERVAL_MILLISECONDS") {
+    Ok(milliseconds) => milliseconds.parse::<u64>().map(Duration::from_millis).map_err(|e| {
+      format!(
+        "invalid value {milliseconds} for environment variable EXCHANGE_RATE_BACKFILL_REQUEST_INTERVAL_MILLISECONDS. {}",
+        e
+      )
+    }),
+    Err(_) => Ok(Duration::from_millis(1000)),
+  }
+}
+
+// Load maximum number of overdue occurrences of a future payment to settle in one execution
+// from environment variable 'FUTURE_PAYMENT_MAX_CATCH_UP_OCCURRENCES', the rest are settled by the following executions
+pub fn load_future_payment_max_catch_up_occurrences() -> Result<usize, String> {
+  match var("FUTURE_PAYMENT_MAX_CATCH_UP_OCCURRENCES") {
+    Ok(occurrences) => occurrences.parse::<usize>().ok().filter(|o| *o > 0).ok_or_else(|| {
+      format!("invalid value {occurrences} for environment variable FUTURE_PAYMENT_MAX_CATCH_UP_OCCURRENCES. expected a positive integer")
+    }),
+    Err(_) => Ok(100),
+  }
+}
+
+// Load path of the file holding trading calendars of markets from environment variable 'MARKET_CALENDAR_FILE'
+pub fn load_market_calendar_file() -> String {
+  var("MARKET_CALENDAR_FILE").unwrap_or_else(|_| "config/market_calendars.json".to_string())
+}
+
+// Load the currency all exchange rates are derived from by environment variable 'EXCHANGE_RATE_PIVOT_CURRENCY', none when it is unset
+pub fn load_exchange_rate_pivot_currency() -> Option<String> {
+  var("EXCHANGE_RATE_PIVOT_CURRENCY")
+    .ok()
+    .filter(|p| !p.is_empty())
+    .map(|p| p.to_lowercase())
+}
+
+#[derive(Debug, Clone)]
+pub struct ExchangeRateSanityCheckConfig {
+  // Maximum change of a rate against its stored value
+  pub max_change_percentage: Decimal,
+  // Maximum deviation of a rate multiplied by its inverse rate from 1
+  pub max_inverse_deviation_percentage: Decimal,
+}
+
+// Load thresholds of exchange rate sanity checks from environment variables
+// 'EXCHANGE_RATE_MAX_CHANGE_PERCENTAGE' and 'EXCHANGE_RATE_MAX_INVERSE_DEVIATION_PERCENTAGE'
+pub fn load_exchange_rate_sanity_check_config() -> Result<ExchangeRateSanityCheckConfig, String> {
+  let load_percentage = |key: &str, default: Decimal| match var(key) {
+    Ok(percentage) => Decimal::from_str(&percentage)
+      .ok()
+      .filter(|p| p.is_sign_positive())
+      .ok_or_else(|| format!("invalid value {percentage} for environment variable {key}. expected a non-negative number")),
+    Err(_) => Ok(default),
+  };
+
+  Ok(ExchangeRateSanityCheckConfig {
+    max_change_percentage: load_percentage("EXCHANGE_RATE_MAX_CHANGE_PERCENTAGE", Decimal::from(20))?,
+    max_inverse_deviation_percentage: load_percentage("EXCHANGE_RATE_MAX_INVERSE_DEVIATION_PERCENTAGE", Decimal::from(1))?,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn default_config() -> CronjobConfig {
+    CronjobConfig {
+      schedule: "0 */10 * * * * *".to_string(),
+      enabled: true,
+      timeout: Some(Duration::from_secs(300)),
+      overlap_policy: OverlapPolicy::Skip,
+      retry_policy: RetryPolicy {
+        max_attempts: 3,
+        base_delay: Duration::from_secs(5),
+        max_delay: Duration::from_secs(60),
+        jitter: 0.5,
+      },
+      catch_up_days: None,
+    }
+  }
+
+  fn vars(entries: &[(&str, &str)]) -> Vec<(String, String)> {
+    entries.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
+  }
+
+  #[test]
+  fn keeps_defaults_without_overrides() {
+    let config = parse_cronjob_config("update_latest_exchange_rates", default_config(), &vars(&[("OTHER", "1")])).unwrap();
+
+    assert_eq!(config.schedule, "0 */10 * * * * *");
+    assert!(config.enabled);
+    assert_eq!(config.timeout, Some(Duration::from_secs(300)));
+    assert_eq!(config.overlap_policy, OverlapPolicy::Skip);
+    assert_eq!(config.retry_policy.max_attempts, 3);
+  }
+
+  #[test]
+  fn overrides_every_field() {
+    let config = parse_cronjob_config(
+      "update_latest_exchange_rates",
+      default_config(),
+      &vars(&[
+        ("CRONJOB_UPDATE_LATEST_EXCHANGE_RATES_SCHEDULE", "0 */5 * * * * *"),
+        ("CRONJOB_UPDATE_LATEST_EXCHANGE_RATES_ENABLED", "false"),
+        ("CRONJOB_UPDATE_LATEST_EXCHANGE_RATES_TIMEOUT_SECONDS", "0"),
+        ("CRONJOB_UPDATE_LATEST_EXCHANGE_RATES_OVERLAP_POLICY", "queue_one"),
+        ("CRONJOB_UPDATE_LATEST_EXCHANGE_RATES_RETRY_MAX_ATTEMPTS", "5"),
+        ("CRONJOB_UPDATE_LATEST_EXCHANGE_RATES_RETRY_BASE_DELAY_SECONDS", "10"),
+        ("CRONJOB_UPDATE_LATEST_EXCHANGE_RATES_RETRY_MAX_DELAY_SECONDS", "120"),
+        ("CRONJOB_UPDATE_LATEST_EXCHANGE_RATES_RETRY_JITTER", "0.2"),
+      ]),
+    )
+    .unwrap();
+
+    assert_eq!(config.schedule, "0 */5 * * * * *");
+    assert!(!config.enabled);
+    assert_eq!(config.timeout, None);
+    assert_eq!(config.overlap_policy, OverlapPolicy::QueueOne);
+    assert_eq!(config.retry_policy.max_attempts, 5);
+    assert_eq!(config.retry_policy.base_delay, Duration::from_secs(10));
+    assert_eq!(config.retry_policy.max_delay, Duration::from_secs(120));
+    assert_eq!(config.retry_policy.jitter, 0.2);
+  }
+
+  #[test]
+  fn overrides_catch_up_days_of_cronjob_catching_up() {
+    let default = CronjobConfig {
+      catch_up_days: Some(31),
+      ..default_config()
+    };
+    let config = parse_cronjob_config(
+      "record_exchange_rate_snapshots",
+      default,
+      &vars(&[("CRONJOB_RECORD_EXCHANGE_RATE_SNAPSHOTS_CATCH_UP_DAYS", "7")]),
+    )
+    .unwrap();
+
+    assert_eq!(config.catch_up_days, Some(7));
+  }
+
+  #[test]
+  fn rejects_catch_up_days_of_cronjob_not_catching_up() {
+    let result = parse_cronjob_config(
+      "update_latest_exchange_rates",
+      default_config(),
+      &vars(&[("CRONJOB_UPDATE_LATEST_EXCHANGE_RATES_CATCH_UP_DAYS", "7")]),
+    );
+
+    assert!(result.is_err());
+  }
+
+  #[test]
+  fn ignores_overrides_of_other_cronjobs() {
+    let config = parse_cronjob_config(
+      "update_latest_exchange_rates",
+      default_config(),
+      &vars(&[("CRONJOB_MONITOR_FUTURE_PAYMENTS_ENABLED", "false")]),
+    )
+    .unwrap();
+
+    assert!(config.enabled);
+  }
+
+  #[test]
+  fn rejects_malformed_overrides() {
+    for (field, value) in [
+      ("SCHEDULE", "every minute"),
+      ("ENABLED", "yes"),
+      ("TIMEOUT_SECONDS", "-1"),
+      ("OVERLAP_POLICY", "replace"),
+      ("RETRY_MAX_ATTEMPTS", "0"),
+      ("RETRY_BASE_DELAY_SECONDS", "1.5"),
+      ("RETRY_MAX_DELAY_SECONDS", "forever"),
+      ("RETRY_JITTER", "1.5"),
+      ("CATCH_UP_DAYS", "0"),
+    ] {
+      let key = format!("CRONJOB_MONITOR_FUTURE_PAYMENTS_{field}");
+      let default = CronjobConfig {
+        catch_up_days: Some(31),
+        ..default_config()
+      };
+      let result = parse_cronjob_config("monitor_future_payments", default, &vars(&[(&key, value)]));
+
+      assert!(result.is_err(), "{key}={value} should be rejected");
+    }
+  }
+
+  #[test]
+  fn accepts_known_cronjob_configs() {
+    let names = ["update_latest_exchange_rates", "monitor_future_payments"];
+    let vars = vars(&[
+      ("CRONJOB_UPDATE_LATEST_EXCHANGE_RATES_SCHEDULE", "0 */5 * * * * *"),
+      ("CRONJOB_MONITOR_FUTURE_PAYMENTS_RETRY_MAX_ATTEMPTS", "1"),
+      ("DATABASE", "postgres://localhost"),
+    ]);
+
+    assert!(check_unknown_cronjob_config_vars(&names, &vars).is_ok());
+  }
+
+  #[test]
+  fn rejects_unknown_cronjob() {
+    let names = ["update_latest_exchange_rates"];
+    let vars = vars(&[("CRONJOB_UPDATE_LATEST_EXCHANGE_RATE_SCHEDULE", "0 */5 * * * * *")]);
+
+    let error = check_unknown_cronjob_config_vars(&names, &vars).unwrap_err();
+    assert!(error.contains("unknown cronjob update_latest_exchange_rate "), "{error}");
+  }
+
+  #[test]
+  fn rejects_unknown_config_field() {
+    let names = ["update_latest_exchange_rates"];
+    let vars = vars(&[("CRONJOB_UPDATE_LATEST_EXCHANGE_RATES_INTERVAL", "600")]);
+
+    let error = check_unknown_cronjob_config_vars(&names, &vars).unwrap_err();
+    assert!(error.contains("unknown cronjob config field"), "{error}");
+  }
+}
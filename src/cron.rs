@@ -1,53 +1,576 @@
 // mod balance;
+pub mod anomaly;
+pub mod backfill;
 mod exchange_rate;
 mod future_payment;
+mod history;
+pub mod lock;
+mod market_calendar;
+mod recurrence;
+pub mod retry;
 mod stock;
+mod stock_price_history;
+mod summary;
 
+use crate::config::{self, CronjobConfig};
+use crate::external::db::query::country::get_all_countries_with_stocks;
+use crate::external::db::query::paused_cronjob::{is_cronjob_paused, pause_cronjob, resume_cronjob};
+use crate::external::exchange_rate_provider::ExchangeRateProviders;
+use crate::external::yahoo_finance::YahooFinanceClient;
+use chrono::{Datelike, Utc};
+use history::JobRunOutcome;
+use lock::ClusterLock;
+use mongodb::Client;
+use retry::RetryPolicy;
+use sqlx::{Pool, Postgres};
 use std::error::Error;
-use std::future::Future;
+use std::fmt;
+use std::future::{pending, Future};
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use summary::RunSummary;
 use time::{format_description, OffsetDateTime};
+use tokio::sync::{Mutex, Notify};
+use tokio::time::{sleep, timeout};
 use tokio_cron_scheduler::{Job, JobScheduler, JobSchedulerError};
-use tracing::{debug, error};
+use tracing::{debug, error, info, warn};
+use uuid::Uuid;
 
 // Cronjob Syntax
 // sec   min   hour   day of month   month   day of week   year
 // *     *     *      *              *       *             *
 
-static CRONJOB_NAMES: [&str; 5] = [
-  "record_exchange_rate_snapshots",
-  "update_latest_exchange_rates",
-  "update_latest_us_stock_prices",
-  "monitor_future_payments",
-  "update_latest_uk_stock_prices",
-];
+type CronjobTask =
+  Arc<dyn Fn(JobContext, &CronjobConfig) -> Pin<Box<dyn Future<Output = Result<RunSummary, Box<dyn Error>>> + Send>> + Send + Sync>;
 
-#[tracing::instrument]
-pub async fn init() {
+// Clients shared by all cronjob tasks so that they do not need to open their own connections on every execution
+#[derive(Clone, Debug)]
+pub struct JobContext {
+  pub pg_client: Pool<Postgres>,
+  pub mdb_client: Client,
+  pub exchange_rate_providers: ExchangeRateProviders,
+  pub yahoo_finance_client: Arc<YahooFinanceClient>,
+}
+
+// Keep track of running executions across all cronjobs so that shutdown can wait for them to finish
+#[derive(Default)]
+struct ExecutionTracker {
+  shutting_down: AtomicBool,
+  // Set once the grace period of shutdown is over, so that the executions still running are cancelled
+  cancelling: AtomicBool,
+  running: AtomicUsize,
+  shutdown_requested: Notify,
+  cancel_requested: Notify,
+  finished: Notify,
+}
+
+// Time given to cancelled executions to record their outcome and roll back their database transactions
+const CANCELLATION_TIMEOUT: Duration = Duration::from_secs(3);
+
+impl ExecutionTracker {
+  // Count a new execution as running, unless the service is shutting down
+  fn start(self: &Arc<Self>) -> Option<RunningExecution> {
+    // Count the execution as running before checking for shutdown so that shutdown never misses it
+    self.running.fetch_add(1, Ordering::SeqCst);
+    let running_execution = RunningExecution(self.clone());
+    (!self.shutting_down.load(Ordering::SeqCst)).then_some(running_execution)
+  }
+
+  async fn shutdown_requested(&self) {
+    // Register for notification before checking the flag so that the shutdown is never missed
+    let shutdown_requested = self.shutdown_requested.notified();
+    if !self.shutting_down.load(Ordering::SeqCst) {
+      shutdown_requested.await;
+    }
+  }
+
+  // Resolve once the running executions should be cancelled
+  async fn cancelled(&self) {
+    // Register for notification before checking the flag so that the cancellation is never missed
+    let cancel_requested = self.cancel_requested.notified();
+    if !self.cancelling.load(Ordering::SeqCst) {
+      cancel_requested.await;
+    }
+  }
+
+  async fn wait_for_running_executions(&self) {
+    loop {
+      // Register for notification before checking the count so that no finished execution is missed
+      let finished = self.finished.notified();
+      let running = self.running.load(Ordering::SeqCst);
+      if running == 0 {
+        break;
+      }
+      info!("waiting for {running} running executions to finish");
+      finished.await;
+    }
+  }
+}
+
+// Mark an execution as finished when dropped, including when the execution is cancelled
+struct RunningExecution(Arc<ExecutionTracker>);
+
+impl Drop for RunningExecution {
+  fn drop(&mut self) {
+    self.0.running.fetch_sub(1, Ordering::SeqCst);
+    self.0.finished.notify_waiters();
+  }
+}
+
+// Execution running in background outside of cronjob schedule, e.g. exchange rate backfill, which shutdown waits for as well
+pub struct BackgroundExecution {
+  tracker: Arc<ExecutionTracker>,
+  _running_execution: RunningExecution,
+}
+
+impl BackgroundExecution {
+  pub fn is_shutting_down(&self) -> bool {
+    self.tracker.shutting_down.load(Ordering::SeqCst)
+  }
+
+  // Resolve once the service starts shutting down, when the execution should stop at the next point it can be resumed from
+  pub async fn shutdown_requested(&self) {
+    self.tracker.shutdown_requested().await
+  }
+
+  // Resolve once the grace period of shutdown is over, when the execution is about to be cut off
+  pub async fn cancelled(&self) {
+    self.tracker.cancelled().await
+  }
+}
+
+// Decide what happens when a cronjob is triggered while its previous execution is still running
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverlapPolicy {
+  // Skip the new execution
+  Skip,
+  // Run the new execution after the running one finishes, skip any more than one waiting
+  QueueOne,
+  // Run the new execution without waiting for the running one, neither in this process nor on other instances
+  Allow,
+}
+
+impl fmt::Display for OverlapPolicy {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let overlap_policy = match self {
+      OverlapPolicy::Skip => "skip",
+      OverlapPolicy::QueueOne => "queue_one",
+      OverlapPolicy::Allow => "allow",
+    };
+    write!(f, "{overlap_policy}")
+  }
+}
+
+impl FromStr for OverlapPolicy {
+  type Err = String;
+
+  fn from_str(value: &str) -> Result<Self, Self::Err> {
+    match value {
+      "skip" => Ok(OverlapPolicy::Skip),
+      "queue_one" => Ok(OverlapPolicy::QueueOne),
+      "allow" => Ok(OverlapPolicy::Allow),
+      _ => Err(format!("unknown overlap policy {value}. expected one of skip, queue_one or allow")),
+    }
+  }
+}
+
+pub struct Cronjob {
+  pub name: &'static str,
+  instance_id: Uuid,
+  pub config: CronjobConfig,
+  queued: AtomicBool,
+  execution_lock: Mutex<()>,
+  task: CronjobTask,
+  context: JobContext,
+  tracker: Arc<ExecutionTracker>,
+}
+
+// Handle to all the cronjobs registered in scheduler so that they can be managed at runtime
+#[derive(Clone)]
+pub struct CronjobRegistry {
+  scheduler: JobScheduler,
+  // Disabled cronjobs are not added into scheduler but can still be triggered on demand
+  cronjobs: Arc<Vec<(Option<Uuid>, Arc<Cronjob>)>>,
+  tracker: Arc<ExecutionTracker>,
+  // Held for the lifetime of the service so that other instances know its job runs are still alive
+  _instance_lock: Arc<ClusterLock>,
+}
+
+impl Cronjob {
+  // Paused state is kept in database so that it is shared by every instance and survives restarts
+  pub async fn is_paused(&self) -> Result<bool, String> {
+    is_cronjob_paused(&self.context.pg_client, self.name).await
+  }
+
+  pub async fn pause(&self) -> Result<(), String> {
+    pause_cronjob(&self.context.pg_client, self.name).await
+  }
+
+  pub async fn resume(&self) -> Result<(), String> {
+    resume_cronjob(&self.context.pg_client, self.name).await
+  }
+
+  fn is_shutting_down(&self) -> bool {
+    self.tracker.shutting_down.load(Ordering::SeqCst)
+  }
+
+  // Execute the cronjob task once and record the execution in job run history
+  // Executions triggered on demand are not tied to any tick of the cron schedule
+  pub async fn execute(&self, on_demand: bool) {
+    let name = self.name;
+    let schedule = (!on_demand).then_some(self.config.schedule.as_str());
+
+    let Some(_running_execution) = self.tracker.start() else {
+      info!("service is shutting down. skipped executing cronjob {name}");
+      return;
+    };
+
+    // Make sure the previous execution in this process has finished according to overlap policy
+    let _execution_guard = match self.config.overlap_policy {
+      OverlapPolicy::Allow => None,
+      OverlapPolicy::Skip => match self.execution_lock.try_lock() {
+        Ok(execution_guard) => Some(execution_guard),
+        Err(_) => {
+          info!("cronjob {name} is still running. skipped this tick");
+          history::skip_job_run(
+            &self.context.pg_client,
+            self.instance_id,
+            name,
+            schedule,
+            "previous execution is still running",
+          )
+          .await;
+          return;
+        }
+      },
+      OverlapPolicy::QueueOne => match self.execution_lock.try_lock() {
+        Ok(execution_guard) => Some(execution_guard),
+        Err(_) if self.queued.swap(true, Ordering::SeqCst) => {
+          info!("cronjob {name} is still running with another execution queued. skipped this tick");
+          history::skip_job_run(
+            &self.context.pg_client,
+            self.instance_id,
+            name,
+            schedule,
+            "previous execution is still running and another one is queued",
+          )
+          .await;
+          return;
+        }
+        Err(_) => {
+          debug!("cronjob {name} is still running. queued this tick");
+          let execution_guard = self.execution_lock.lock().await;
+          self.queued.store(false, Ordering::SeqCst);
+          if self.is_shutting_down() {
+            info!("service is shutting down. skipped executing queued cronjob {name}");
+            return;
+          }
+          Some(execution_guard)
+        }
+      },
+    };
+
+    // Take a cluster-wide lock so that only one execution of the cronjob runs at a time across all instances
+    // Executions allowed to overlap do not take it, as they should neither wait for nor be skipped by each other
+    let mut cluster_lock = match self.config.overlap_policy {
+      OverlapPolicy::Allow => None,
+      OverlapPolicy::Skip | OverlapPolicy::QueueOne => {
+        match ClusterLock::acquire(&self.context.pg_client, &format!("everytrack_cron:{name}")).await {
+          Ok(Some(cluster_lock)) => Some(cluster_lock),
+          Ok(None) => {
+            info!("cronjob {name} is being executed elsewhere. skipped this tick");
+            history::skip_job_run(
+              &self.context.pg_client,
+              self.instance_id,
+              name,
+              schedule,
+              "cronjob is being executed elsewhere",
+            )
+            .await;
+            return;
+          }
+          Err(e) => {
+            error!("{}. skipped this tick", e);
+            return;
+          }
+        }
+      }
+    };
+
+    debug!(
+      "start executing cronjob {name} at {}",
+      OffsetDateTime::now_utc()
+        .format(&format_description::parse("[year]-[month]-[day]T[hour]:[minute]:[second]Z").unwrap())
+        .unwrap()
+    );
+    let run = history::start_job_run(&self.context.pg_client, self.instance_id, name, schedule).await;
+    // Bound the whole execution including retries by timeout, the task future is dropped and hence cancelled when time is up
+    let mut attempts = 0;
+    let execution = async {
+      match self.config.timeout {
+        Some(duration) => match timeout(duration, self.execute_with_retry(&mut attempts)).await {
+          Ok(outcome) => outcome,
+          Err(_) => {
+            error!("cronjob {name} timed out after {}s. cancelled the execution", duration.as_secs());
+            JobRunOutcome::TimedOut(format!("timed out after {}s", duration.as_secs()))
+          }
+        },
+        None => self.execute_with_retry(&mut attempts).await,
+      }
+    };
+    // The task future is dropped the same way when it is still running after the grace period of shutdown or the lock is lost
+    let outcome = tokio::select! {
+      outcome = execution => outcome,
+      _ = self.tracker.cancelled() => {
+        error!("cronjob {name} is still running after grace period of shutdown. cancelled the execution");
+        JobRunOutcome::Failed("cancelled as service shut down before the execution finished".to_string())
+      }
+      reason = async {
+        match cluster_lock.as_mut() {
+          Some(cluster_lock) => cluster_lock.lost().await,
+          None => pending().await,
+        }
+      } => {
+        error!("cronjob {name} lost its lock. cancelled the execution. {reason}");
+        JobRunOutcome::Failed(format!("cancelled as {reason}"))
+      }
+    };
+    match outcome {
+      JobRunOutcome::Skipped(_) => history::discard_job_run(&self.context.pg_client, &run).await,
+      _ => history::finish_job_run_with_outcome(&self.context.pg_client, &run, &outcome, attempts).await,
+    }
+    if let Some(cluster_lock) = cluster_lock {
+      cluster_lock.release().await;
+    }
+  }
+
+  // Retry the task with exponential backoff as long as it fails with retryable error
+  async fn execute_with_retry(&self, attempts: &mut u32) -> JobRunOutcome {
+    let name = self.name;
+    loop {
+      *attempts += 1;
+      let result = (self.task)(self.context.clone(), &self.config).await.map_err(|e| {
+        let should_retry = self.config.retry_policy.should_retry(e.as_ref(), *attempts);
+        (should_retry, retry::is_skipped(e.as_ref()), e.to_string())
+      });
+      match result {
+        Ok(summary) => return JobRunOutcome::Succeeded(summary),
+        Err((_, true, reason)) => {
+          debug!("cronjob {name} skipped this tick. {reason}");
+          return JobRunOutcome::Skipped(reason);
+        }
+        Err((true, _, e)) => {
+          let delay = self.config.retry_policy.get_backoff_delay(*attempts);
+          warn!(
+            "cronjob {name} failed on attempt {attempts}. going to retry in {}ms. {}",
+            delay.as_millis(),
+            e
+          );
+          // Do not hold up shutdown by waiting for another attempt
+          tokio::select! {
+            _ = sleep(delay) => continue,
+            _ = self.tracker.shutdown_requested() => {}
+          }
+          error!("cronjob {name} will not be retried as service is shutting down");
+          return JobRunOutcome::Failed(e);
+        }
+        Err((_, _, e)) => {
+          error!("{}", e);
+          return JobRunOutcome::Failed(e);
+        }
+      }
+    }
+  }
+}
+
+impl CronjobRegistry {
+  pub fn list(&self) -> Vec<Arc<Cronjob>> {
+    self.cronjobs.iter().map(|(_, cronjob)| cronjob.clone()).collect()
+  }
+
+  pub fn get(&self, name: &str) -> Option<Arc<Cronjob>> {
+    self
+      .cronjobs
+      .iter()
+      .find(|(_, cronjob)| cronjob.name == name)
+      .map(|(_, cronjob)| cronjob.clone())
+  }
+
+  pub async fn next_tick(&self, name: &str) -> Option<OffsetDateTime> {
+    let (id, _) = self.cronjobs.iter().find(|(_, cronjob)| cronjob.name == name)?;
+    let mut scheduler = self.scheduler.clone();
+    match scheduler.next_tick_for_job((*id)?).await {
+      Ok(Some(timestamp)) => OffsetDateTime::from_unix_timestamp(timestamp.timestamp()).ok(),
+      _ => None,
+    }
+  }
+
+  // Returns none when the service is shutting down, in which case no more execution should be started
+  pub fn start_background_execution(&self) -> Option<BackgroundExecution> {
+    let running_execution = self.tracker.start()?;
+    Some(BackgroundExecution {
+      tracker: self.tracker.clone(),
+      _running_execution: running_execution,
+    })
+  }
+
+  // Stop scheduling new executions and wait for running ones to finish within the grace period
+  // Executions still running after the grace period are cancelled
+  #[tracing::instrument(skip(self))]
+  pub async fn shutdown(&self, grace_period: Duration) {
+    self.tracker.shutting_down.store(true, Ordering::SeqCst);
+    self.tracker.shutdown_requested.notify_waiters();
+    let mut scheduler = self.scheduler.clone();
+    match scheduler.shutdown().await {
+      Ok(_) => debug!("cronjob scheduler stopped"),
+      Err(e) => error!("failed to stop cronjob scheduler. {}", e),
+    };
+
+    if timeout(grace_period, self.tracker.wait_for_running_executions()).await.is_ok() {
+      info!("all running executions finished");
+      return;
+    }
+    warn!(
+      "{} executions are still running after grace period of {}s. going to cancel them",
+      self.tracker.running.load(Ordering::SeqCst),
+      grace_period.as_secs()
+    );
+    self.tracker.cancelling.store(true, Ordering::SeqCst);
+    self.tracker.cancel_requested.notify_waiters();
+    match timeout(CANCELLATION_TIMEOUT, self.tracker.wait_for_running_executions()).await {
+      Ok(_) => info!("all running executions cancelled"),
+      Err(_) => error!(
+        "{} executions did not stop within {}s after being cancelled",
+        self.tracker.running.load(Ordering::SeqCst),
+        CANCELLATION_TIMEOUT.as_secs()
+      ),
+    };
+  }
+}
+
+#[tracing::instrument(skip(context))]
+pub async fn init(context: JobContext) -> CronjobRegistry {
   let scheduler = JobScheduler::new().await.expect("Failed to initialize cronjob scheduler");
   debug!("initialized cronjob scheduler");
 
-  // Create cronjobs
-  let cronjobs = vec![
-    // Record exchange rate snapshots every day at 00:00
-    create_cronjob(CRONJOB_NAMES[0], "0 0 0 * * * *", exchange_rate::record_exchange_rate_snapshots),
+  // Job runs left running by previous processes are settled before this instance records its own
+  let instance_id = Uuid::new_v4();
+  let instance_lock = ClusterLock::acquire(&context.pg_client, &history::get_instance_lock_key(instance_id))
+    .await
+    .and_then(|instance_lock| instance_lock.ok_or("lock of a new instance is held elsewhere".to_string()))
+    .unwrap_or_else(|e| panic!("Failed to register cronjob instance {instance_id}. {}", e));
+  if let Err(e) = history::interrupt_orphaned_job_runs(&context.pg_client).await {
+    error!("failed to interrupt job runs left running by stopped instances. {}", e);
+  }
+
+  // Define all cronjobs with their default config, which can be overridden by environment variables
+  let mut cronjob_definitions: Vec<(&'static str, CronjobConfig, CronjobTask)> = vec![
+    // Record exchange rate snapshots every day at 00:00, catching up on the missing ones of the last 31 days
+    // A delayed snapshot should still be recorded, so queue it up if the previous one is still running
+    (
+      "record_exchange_rate_snapshots",
+      CronjobConfig {
+        catch_up_days: Some(31),
+        ..default_cronjob_config("0 0 0 * * * *", OverlapPolicy::QueueOne, 900, default_retry_policy(5, 30, 600))
+      },
+      to_configured_cronjob_task(|context, config| {
+        exchange_rate::record_exchange_rate_snapshots(context, config.catch_up_days.unwrap_or(1))
+      }),
+    ),
     // Update latest exchange rates every 10 minutes
-    create_cronjob(CRONJOB_NAMES[1], "0 */10 * * * * *", exchange_rate::update_latest_exchange_rates),
-    // Update latest us stock prices every 10 minutes
-    create_cronjob(CRONJOB_NAMES[2], "0 */10 * * * * *", stock::update_latest_us_stock_prices),
+    (
+      "update_latest_exchange_rates",
+      default_cronjob_config("0 */10 * * * * *", OverlapPolicy::Skip, 300, default_retry_policy(3, 5, 60)),
+      to_cronjob_task(exchange_rate::update_latest_exchange_rates),
+    ),
     // Monitor future payment and update account balances + create transactions every hour
-    create_cronjob(CRONJOB_NAMES[3], "0 0 * * * * *", future_payment::monitor_future_payments),
-    // Update latest uk stock prices every 10 minutes
-    create_cronjob(CRONJOB_NAMES[4], "0 */10 * * * * *", stock::update_latest_uk_stock_prices),
+    (
+      "monitor_future_payments",
+      default_cronjob_config("0 0 * * * * *", OverlapPolicy::Skip, 1800, default_retry_policy(3, 10, 120)),
+      to_cronjob_task(future_payment::monitor_future_payments),
+    ),
+    // Record daily price bars of stocks every day at 00:30, after the markets of the previous day have closed
+    // Stocks without any price history get their full history backfilled, which can take a while
+    (
+      "record_stock_price_history",
+      default_cronjob_config("0 30 0 * * * *", OverlapPolicy::Skip, 3600, default_retry_policy(3, 30, 600)),
+      to_cronjob_task(stock_price_history::record_stock_price_history),
+    ),
   ];
+  // Update latest stock prices of each market every 10 minutes, e.g. update_latest_us_stock_prices
+  // Markets are the countries having stocks in database at startup
+  let countries = get_all_countries_with_stocks(&context.pg_client)
+    .await
+    .unwrap_or_else(|e| panic!("Failed to get markets for stock price cronjobs. {}", e));
+  // Ticks are skipped while the market is closed according to its trading calendar
+  let mut market_calendars = market_calendar::load_market_calendars().unwrap_or_else(|e| panic!("Invalid market calendars. {}", e));
+  for country in countries.into_iter() {
+    // Cronjobs live as long as the service, so leaking the names makes them static like the others
+    let name: &'static str = Box::leak(format!("update_latest_{}_stock_prices", country.code.to_lowercase()).into_boxed_str());
+    let market_calendar = market_calendars.remove(&country.code.to_uppercase()).map(Arc::new);
+    match market_calendar.as_ref() {
+      None => warn!(
+        "no trading calendar for market {}. its stock prices will be updated regardless of trading hours",
+        country.code
+      ),
+      // Holidays have to be extended every year, otherwise they are treated as trading days
+      Some(market_calendar) => {
+        let current_year = Utc::now().with_timezone(&market_calendar.timezone).year();
+        if !market_calendar.has_holidays_in_year(current_year) {
+          warn!(
+            "trading calendar for market {} has no holidays in {current_year}. its stock prices will be updated on holidays as on trading days until they are added",
+            country.code
+          );
+        }
+      }
+    }
+    let country_code = country.code;
+    cronjob_definitions.push((
+      name,
+      default_cronjob_config("0 */10 * * * * *", OverlapPolicy::Skip, 540, default_retry_policy(3, 5, 60)),
+      to_cronjob_task(move |context| {
+        let country_code = country_code.clone();
+        let market_calendar = market_calendar.clone();
+        async move { stock::update_latest_stock_prices(context, &country_code, market_calendar).await }
+      }),
+    ));
+  }
+  let cronjob_names = cronjob_definitions.iter().map(|(name, _, _)| *name).collect::<Vec<&str>>();
+  config::check_unknown_cronjob_configs(&cronjob_names).unwrap_or_else(|e| panic!("Invalid cronjob config. {}", e));
   debug!("going to add jobs to cronjob scheduler");
 
-  // Adding jobs to scheduler
-  for (index, cronjob) in cronjobs.into_iter().flatten().enumerate() {
-    debug!("added cronjob {} into cronjob scheduler", CRONJOB_NAMES[index]);
-    scheduler
-      .add(cronjob)
+  // Adding enabled jobs to scheduler
+  let tracker = Arc::new(ExecutionTracker::default());
+  let mut registered_cronjobs = vec![];
+  for (name, default_config, task) in cronjob_definitions.into_iter() {
+    let cronjob = Arc::new(Cronjob {
+      name,
+      task,
+      instance_id,
+      config: config::load_cronjob_config(name, default_config).unwrap_or_else(|e| panic!("Invalid cronjob config. {}", e)),
+      queued: AtomicBool::new(false),
+      execution_lock: Mutex::new(()),
+      context: context.clone(),
+      tracker: tracker.clone(),
+    });
+    if !cronjob.config.enabled {
+      debug!("cronjob {name} is disabled. will not add it into cronjob scheduler");
+      registered_cronjobs.push((None, cronjob));
+      continue;
+    }
+    let job = create_job(cronjob.clone()).unwrap_or_else(|e| panic!("Failed to create cronjob {name}. {}", e));
+    let id = scheduler
+      .add(job)
       .await
-      .unwrap_or_else(|e| panic!("Failed to add cronjob of recording exchange rate snapshots. {}", e));
+      .unwrap_or_else(|e| panic!("Failed to add cronjob {name} into cronjob scheduler. {}", e));
+    debug!(
+      "added cronjob {name} into cronjob scheduler with schedule {}",
+      cronjob.config.schedule
+    );
+    registered_cronjobs.push((Some(id), cronjob));
   }
   debug!("added all cronjobs into cronjob scheduler");
 
@@ -56,24 +579,72 @@ pub async fn init() {
     Ok(_) => debug!("cronjob scheduler started"),
     Err(e) => panic!("Failed to start the scheduler. {}", e),
   };
+
+  CronjobRegistry {
+    scheduler,
+    tracker,
+    cronjobs: Arc::new(registered_cronjobs),
+    _instance_lock: Arc::new(instance_lock),
+  }
 }
 
-fn create_cronjob<F, Fut>(name: &'static str, schedule: &'static str, task: F) -> Result<Job, JobSchedulerError>
+fn default_cronjob_config(schedule: &str, overlap_policy: OverlapPolicy, timeout_seconds: u64, retry_policy: RetryPolicy) -> CronjobConfig {
+  CronjobConfig {
+    overlap_policy,
+    retry_policy,
+    enabled: true,
+    schedule: schedule.to_string(),
+    timeout: Some(Duration::from_secs(timeout_seconds)),
+    catch_up_days: None,
+  }
+}
+
+fn default_retry_policy(max_attempts: u32, base_delay_seconds: u64, max_delay_seconds: u64) -> RetryPolicy {
+  RetryPolicy {
+    max_attempts,
+    jitter: 0.5,
+    base_delay: Duration::from_secs(base_delay_seconds),
+    max_delay: Duration::from_secs(max_delay_seconds),
+  }
+}
+
+fn to_cronjob_task<F, Fut, T>(task: F) -> CronjobTask
 where
-  F: Fn() -> Fut + Clone + Send + Sync + 'static,
-  Fut: Future<Output = Result<(), Box<dyn Error>>> + Send,
+  F: Fn(JobContext) -> Fut + Send + Sync + 'static,
+  Fut: Future<Output = Result<T, Box<dyn Error>>> + Send + 'static,
+  T: Into<RunSummary>,
 {
-  Job::new_async(schedule, move |uuid, mut l| {
-    let task = task.clone();
+  to_configured_cronjob_task(move |context, _| task(context))
+}
+
+// Task taking settings from the config of its cronjob, e.g. how many days to catch up on
+fn to_configured_cronjob_task<F, Fut, T>(task: F) -> CronjobTask
+where
+  F: Fn(JobContext, &CronjobConfig) -> Fut + Send + Sync + 'static,
+  Fut: Future<Output = Result<T, Box<dyn Error>>> + Send + 'static,
+  T: Into<RunSummary>,
+{
+  Arc::new(move |context, config| {
+    let task = task(context, config);
+    Box::pin(async move { task.await.map(Into::into) })
+  })
+}
+
+fn create_job(cronjob: Arc<Cronjob>) -> Result<Job, JobSchedulerError> {
+  let name = cronjob.name;
+  let schedule = cronjob.config.schedule.clone();
+  Job::new_async(schedule.as_str(), move |uuid, mut l| {
+    let cronjob = cronjob.clone();
     Box::pin(async move {
-      debug!(
-        "start executing cronjob {name} at {}",
-        OffsetDateTime::now_utc()
-          .format(&format_description::parse("[year]-[month]-[day]T[hour]:[minute]:[second]Z").unwrap())
-          .unwrap()
-      );
-      if let Err(e) = task().await {
-        error!("{}", e);
+      // Checked before the execution takes any lock, so that a paused cronjob does not hold up the other instances
+      // A tick runs as scheduled when the paused state cannot be read, so that a failed read does not stop the cronjob
+      match cronjob.is_paused().await {
+        Ok(true) => debug!("cronjob {name} is paused. skipped this tick"),
+        Ok(false) => cronjob.execute(false).await,
+        Err(e) => {
+          error!("failed to check whether cronjob {name} is paused. executing it anyway. {}", e);
+          cronjob.execute(false).await;
+        }
       }
       match l.next_tick_for_job(uuid).await {
         Ok(Some(timestamp)) => debug!("next scheduled time for cronjob {name} is {timestamp:?}"),
@@ -0,0 +1,168 @@
+use super::exchange_rate::write_latest_exchange_rate;
+use crate::config::ExchangeRateSanityCheckConfig;
+use crate::external::db::query::exchange_rate_quarantine::{
+  update_exchange_rate_quarantine_status, ExchangeRateQuarantine, UpdateExchangeRateQuarantineStatusParams,
+};
+use rust_decimal::Decimal;
+use sqlx::{Pool, Postgres};
+use std::str::FromStr;
+use tracing::info;
+
+// Work out why an exchange rate looks suspicious before writing it, returns nothing when it passes all checks
+pub fn check_exchange_rate(
+  config: &ExchangeRateSanityCheckConfig,
+  rate: &str,
+  previous_rate: Option<&str>,
+  inverse_rate: Option<&str>,
+) -> Vec<String> {
+  let Ok(rate) = Decimal::from_str(rate) else {
+    return vec![format!("rate {rate} is not a number")];
+  };
+  if rate <= Decimal::ZERO {
+    return vec![format!("rate {rate} is not positive")];
+  }
+
+  let mut reasons = vec![];
+  let hundred = Decimal::ONE_HUNDRED;
+  // Stored and inverse rates that cannot be compared with are ignored
+  // An extreme rate overflowing the calculation cannot be checked, so it is held for approval as well
+  let previous_rate = previous_rate.and_then(|r| Decimal::from_str(r).ok()).filter(|r| *r > Decimal::ZERO);
+  if let Some(previous_rate) = previous_rate {
+    let change_percentage = rate
+      .checked_sub(previous_rate)
+      .and_then(|change| change.checked_div(previous_rate))
+      .and_then(|change| change.checked_mul(hundred))
+      .map(|change| change.abs());
+    match change_percentage {
+      Some(change_percentage) if change_percentage > config.max_change_percentage => reasons.push(format!(
+        "rate changed by {}% from {previous_rate}, more than the maximum of {}%",
+        change_percentage.round_dp(2),
+        config.max_change_percentage
+      )),
+      Some(_) => {}
+      None => reasons.push(format!("change of rate from {previous_rate} is too large to be calculated")),
+    }
+  }
+  let inverse_rate = inverse_rate.and_then(|r| Decimal::from_str(r).ok()).filter(|r| *r > Decimal::ZERO);
+  if let Some(inverse_rate) = inverse_rate {
+    let deviation_percentage = rate
+      .checked_mul(inverse_rate)
+      .and_then(|product| product.checked_sub(Decimal::ONE))
+      .and_then(|deviation| deviation.checked_mul(hundred))
+      .map(|deviation| deviation.abs());
+    match deviation_percentage {
+      Some(deviation_percentage) if deviation_percentage > config.max_inverse_deviation_percentage => reasons.push(format!(
+        "rate is inconsistent with inverse rate {inverse_rate} by {}%, more than the maximum of {}%",
+        deviation_percentage.round_dp(2),
+        config.max_inverse_deviation_percentage
+      )),
+      Some(_) => {}
+      None => reasons.push(format!("deviation from inverse rate {inverse_rate} is too large to be calculated")),
+    }
+  }
+
+  reasons
+}
+
+// Write the quarantined exchange rate as the latest one and mark it as approved in one transaction
+// Returns false without writing anything if the quarantine is no longer pending, e.g. it has been superseded by a newer rate
+// passing the checks since it was read, so that an outdated rate is never approved over the stored one
+#[tracing::instrument(skip(pg_client))]
+pub async fn approve_quarantined_exchange_rate(pg_client: &Pool<Postgres>, quarantine: &ExchangeRateQuarantine) -> Result<bool, String> {
+  let mut pg_transaction = pg_client
+    .begin()
+    .await
+    .map_err(|e| format!("failed to begin transaction in postgresql database. {}", e))?;
+  // Updating the status first locks the quarantine until commit, a concurrent supersede waits for it and vice versa
+  let approved = update_exchange_rate_quarantine_status(
+    &mut *pg_transaction,
+    UpdateExchangeRateQuarantineStatusParams {
+      id: quarantine.id,
+      status: "approved".to_string(),
+    },
+  )
+  .await?;
+  if !approved {
+    return Ok(false);
+  }
+  write_latest_exchange_rate(
+    &mut pg_transaction,
+    quarantine.base_currency_id,
+    quarantine.target_currency_id,
+    &quarantine.rate,
+    &quarantine.provider,
+  )
+  .await?;
+  pg_transaction.commit().await.map_err(|e| {
+    format!(
+      "failed to commit transaction of exchange rate quarantine in postgresql database. {}",
+      e
+    )
+  })?;
+  info!(
+    "approved quarantined exchange rate {} for pair {}:{}",
+    quarantine.rate, quarantine.base_currency_id, quarantine.target_currency_id
+  );
+
+  Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn config() -> ExchangeRateSanityCheckConfig {
+    ExchangeRateSanityCheckConfig {
+      max_change_percentage: Decimal::from(20),
+      max_inverse_deviation_percentage: Decimal::from(1),
+    }
+  }
+
+  #[test]
+  fn passes_rate_close_to_previous_and_inverse_rates() {
+    assert!(check_exchange_rate(&config(), "0.8", Some("0.79"), Some("1.25")).is_empty());
+  }
+
+  #[test]
+  fn quarantines_jump_over_threshold() {
+    let reasons = check_exchange_rate(&config(), "1.0", Some("0.8"), None);
+
+    assert_eq!(reasons.len(), 1);
+    assert!(reasons[0].starts_with("rate changed by 25.00% from 0.8"), "{reasons:?}");
+  }
+
+  #[test]
+  fn quarantines_rate_inconsistent_with_inverse_rate() {
+    let reasons = check_exchange_rate(&config(), "0.8", Some("0.8"), Some("1.3"));
+
+    assert_eq!(reasons.len(), 1);
+    assert!(
+      reasons[0].starts_with("rate is inconsistent with inverse rate 1.3 by 4.00%"),
+      "{reasons:?}"
+    );
+  }
+
+  #[test]
+  fn ignores_zero_or_missing_previous_rate() {
+    assert!(check_exchange_rate(&config(), "0.8", None, None).is_empty());
+    assert!(check_exchange_rate(&config(), "0.8", Some("0"), None).is_empty());
+    assert!(check_exchange_rate(&config(), "0.8", Some("not a rate"), None).is_empty());
+  }
+
+  #[test]
+  fn quarantines_invalid_rate() {
+    assert_eq!(check_exchange_rate(&config(), "abc", None, None), ["rate abc is not a number"]);
+    assert_eq!(check_exchange_rate(&config(), "0", Some("0.8"), None), ["rate 0 is not positive"]);
+  }
+
+  #[test]
+  fn quarantines_rate_overflowing_the_checks() {
+    let max = Decimal::MAX.to_string();
+
+    let reasons = check_exchange_rate(&config(), &max, Some("0.0001"), None);
+    assert_eq!(reasons, ["change of rate from 0.0001 is too large to be calculated"]);
+
+    let reasons = check_exchange_rate(&config(), &max, None, Some("2"));
+    assert_eq!(reasons, ["deviation from inverse rate 2 is too large to be calculated"]);
+  }
+}
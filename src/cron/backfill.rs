@@ -0,0 +1,195 @@
+use super::exchange_rate::{
+  count_exchange_rate_snapshots_of_date, count_expected_exchange_rate_snapshots, get_exchange_rate_snapshots_collection,
+  record_exchange_rate_snapshots_of_date,
+};
+use super::history::JobRunStatus;
+use super::lock::ClusterLock;
+use super::retry::RetryPolicy;
+use super::{BackgroundExecution, JobContext};
+use crate::config;
+use crate::external::db::query::exchange_rate_backfill::{
+  create_new_exchange_rate_backfill, update_exchange_rate_backfill_progress, update_exchange_rate_backfill_status,
+  CreateNewExchangeRateBackfillParams, ExchangeRateBackfill, UpdateExchangeRateBackfillProgressParams,
+  UpdateExchangeRateBackfillStatusParams,
+};
+use sqlx::{Pool, Postgres};
+use std::time::Duration;
+use time::{Date, OffsetDateTime};
+use tokio::time::sleep;
+use tracing::{debug, error, info, warn};
+use uuid::Uuid;
+
+// Only one backfill can run at a time across all instances so that the currency API is not hit by several of them
+const EXCHANGE_RATE_BACKFILL_LOCK_KEY: &str = "everytrack_cron:exchange_rate_backfill";
+
+// Take the cluster-wide backfill lock, returns none when another backfill is running
+pub async fn acquire_exchange_rate_backfill_lock(pg_client: &Pool<Postgres>) -> Result<Option<ClusterLock>, String> {
+  ClusterLock::acquire(pg_client, EXCHANGE_RATE_BACKFILL_LOCK_KEY).await
+}
+
+// Record a new backfill covering every day between start date and end date inclusively
+#[tracing::instrument(skip(pg_client))]
+pub async fn create_exchange_rate_backfill(pg_client: &Pool<Postgres>, start_date: Date, end_date: Date) -> Result<Uuid, String> {
+  let id = Uuid::new_v4();
+  create_new_exchange_rate_backfill(
+    pg_client,
+    CreateNewExchangeRateBackfillParams {
+      id,
+      start_date,
+      end_date,
+      status: JobRunStatus::Running.to_string(),
+      total_days: ((end_date - start_date).whole_days() + 1) as i32,
+    },
+  )
+  .await?;
+
+  Ok(id)
+}
+
+// Populate exchange rate snapshots for every day of the backfill, continuing from the last completed date
+// Days that already have snapshots of all currency pairs are skipped, so a failed or interrupted backfill can be resumed safely
+#[tracing::instrument(skip(context, cluster_lock, execution))]
+pub async fn run_exchange_rate_backfill(
+  context: JobContext,
+  backfill: ExchangeRateBackfill,
+  mut cluster_lock: ClusterLock,
+  execution: BackgroundExecution,
+) {
+  let id = backfill.id;
+  // The backfill stops by itself between days on shutdown, it is only cut off when a day takes longer than the grace period
+  // It also stops as soon as the lock is lost
+  let result = tokio::select! {
+    result = backfill_exchange_rate_snapshots(&context, &backfill, &execution) => result,
+    _ = execution.cancelled() => Ok(false),
+    reason = cluster_lock.lost() => Err(format!("stopped as {reason}")),
+  };
+  let (status, error) = match result {
+    Ok(true) => {
+      info!(
+        "exchange rate backfill {id} from {} to {} completed",
+        backfill.start_date, backfill.end_date
+      );
+      (JobRunStatus::Succeeded, None)
+    }
+    Ok(false) => {
+      warn!("exchange rate backfill {id} was interrupted as service is shutting down. it can be resumed through admin API");
+      (JobRunStatus::Interrupted, None)
+    }
+    Err(e) => {
+      error!("exchange rate backfill {id} failed. {}", e);
+      (JobRunStatus::Failed, Some(e))
+    }
+  };
+  if let Err(e) = update_exchange_rate_backfill_status(
+    &context.pg_client,
+    UpdateExchangeRateBackfillStatusParams {
+      id,
+      error,
+      status: status.to_string(),
+    },
+  )
+  .await
+  {
+    error!("failed to record outcome of exchange rate backfill {id}. {}", e);
+  }
+
+  cluster_lock.release().await;
+}
+
+// Returns false when the service started shutting down before every day is processed
+async fn backfill_exchange_rate_snapshots(
+  context: &JobContext,
+  backfill: &ExchangeRateBackfill,
+  execution: &BackgroundExecution,
+) -> Result<bool, String> {
+  let id = backfill.id;
+  let request_interval = config::load_exchange_rate_backfill_request_interval()?;
+  let retry_policy = RetryPolicy {
+    max_attempts: 5,
+    jitter: 0.5,
+    base_delay: Duration::from_secs(5),
+    max_delay: Duration::from_secs(120),
+  };
+  let collection = get_exchange_rate_snapshots_collection(&context.mdb_client);
+
+  let expected_snapshots_count = count_expected_exchange_rate_snapshots(&context.pg_client).await?;
+
+  let mut completed_days = backfill.completed_days;
+  let mut skipped_days = backfill.skipped_days;
+  let mut date = match backfill.last_completed_date {
+    Some(last_completed_date) => last_completed_date.next_day().unwrap(),
+    None => backfill.start_date,
+  };
+  while date <= backfill.end_date {
+    if execution.is_shutting_down() {
+      return Ok(false);
+    }
+
+    let existing_snapshots_count = count_exchange_rate_snapshots_of_date(&collection, date)
+      .await
+      .map_err(|e| e.to_string())?;
+    let is_fetched = existing_snapshots_count < expected_snapshots_count;
+    if is_fetched {
+      let mut attempts = 0;
+      loop {
+        attempts += 1;
+        let result = record_exchange_rate_snapshots_of_date(context, &collection, date)
+          .await
+          .map_err(|e| (retry_policy.should_retry(e.as_ref(), attempts), e.to_string()));
+        match result {
+          Ok(_) => break,
+          Err((true, e)) => {
+            let delay = retry_policy.get_backoff_delay(attempts);
+            warn!(
+              "exchange rate backfill {id} failed on {date} at attempt {attempts}. going to retry in {}ms. {}",
+              delay.as_millis(),
+              e
+            );
+            tokio::select! {
+              _ = sleep(delay) => {}
+              _ = execution.shutdown_requested() => return Ok(false),
+            }
+          }
+          Err((_, e)) => return Err(format!("failed to backfill exchange rate snapshots of {date}. {}", e)),
+        }
+      }
+      completed_days += 1;
+    } else {
+      debug!("exchange rate snapshots of {date} already exist. skipped it");
+      skipped_days += 1;
+    }
+
+    // Persist the progress so that the backfill can be resumed from the next date
+    update_exchange_rate_backfill_progress(
+      &context.pg_client,
+      UpdateExchangeRateBackfillProgressParams {
+        id,
+        completed_days,
+        skipped_days,
+        last_completed_date: date,
+      },
+    )
+    .await?;
+    info!(
+      "exchange rate backfill {id} processed {date}. {}/{} days done",
+      completed_days + skipped_days,
+      backfill.total_days
+    );
+    date = date.next_day().unwrap();
+
+    // Throttle the requests so that the currency API does not rate limit us
+    if is_fetched && date <= backfill.end_date {
+      tokio::select! {
+        _ = sleep(request_interval) => {}
+        _ = execution.shutdown_requested() => return Ok(false),
+      }
+    }
+  }
+
+  Ok(true)
+}
+
+// Latest date that can be backfilled, as the rates of today are not finalized yet
+pub fn get_latest_backfillable_date() -> Date {
+  OffsetDateTime::now_utc().date().previous_day().unwrap()
+}
@@ -1,191 +1,698 @@
-use crate::external::db::client;
+use super::anomaly::check_exchange_rate;
+use super::retry::JobError;
+use super::summary::{RunSummary, WriteReport};
+use super::JobContext;
+use crate::config;
 use crate::external::db::query::currency::{get_all_currencies, Currency};
 use crate::external::db::query::exchange_rate::{
-  check_existing_exchange_rate, create_new_exchange_rate, update_exchange_rate, CheckExistingExchangeRateParams,
-  CreateNewExchangeRateParams, UpdateExchangeRateParams,
+  check_existing_exchange_rate, create_new_exchange_rate, get_all_exchange_rates, update_exchange_rate, upsert_exchange_rate_source,
+  CheckExistingExchangeRateParams, CreateNewExchangeRateParams, UpdateExchangeRateParams, UpsertExchangeRateSourceParams,
 };
-use dotenvy::var;
+use crate::external::db::query::exchange_rate_quarantine::{
+  quarantine_exchange_rate, supersede_pending_exchange_rate_quarantine, QuarantineExchangeRateParams,
+  SupersedePendingExchangeRateQuarantineParams,
+};
+use crate::external::exchange_rate_provider::{ExchangeRateDate, ExchangeRateProviders};
+use mongodb::bson::doc;
+use mongodb::options::{FindOneOptions, ReplaceOptions};
+use mongodb::{Client, Collection};
+use rust_decimal::prelude::FromPrimitive;
+use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
-use sqlx::{Pool, Postgres};
+use sqlx::{Pool, Postgres, Transaction};
 use std::collections::HashMap;
 use std::error::Error;
 use time::{format_description, Date, Duration, OffsetDateTime, Time};
-use tracing::debug;
+use tracing::{debug, info, warn};
 use uuid::Uuid;
 
+// Exchange rates keyed by target currency as served by the named provider
+type ProviderExchangeRates = (&'static str, HashMap<String, f64>);
+
 #[derive(Debug, Serialize, Deserialize)]
 struct ExchangeRateRecord {
   rate: String,
   base_currency_id: String,
   target_currency_id: String,
+  provider: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
-struct ExchangeRateSnapshot {
+pub(super) struct ExchangeRateSnapshot {
   _id: String,
   date: i64,
   rate: String,
   base_currency_id: String,
   target_currency_id: String,
+  // Snapshots recorded before introducing exchange rate providers do not have it
+  provider: Option<String>,
 }
 
-#[tracing::instrument]
-pub async fn record_exchange_rate_snapshots() -> Result<(), Box<dyn Error>> {
-  // Setup postgresql database connection
-  let pg_client = client::init_pg().await?;
+#[tracing::instrument(skip(context))]
+pub async fn record_exchange_rate_snapshots(context: JobContext, catch_up_days: usize) -> Result<RunSummary, Box<dyn Error>> {
+  let collection = get_exchange_rate_snapshots_collection(&context.mdb_client);
+
+  // Calculate yesterday, which is the latest date that should have a snapshot
+  let yesterday = OffsetDateTime::now_utc().checked_sub(Duration::days(1)).unwrap().date();
+
+  // Find out the recent dates missing snapshot of any currency pair, e.g. the service was down or some pairs failed
+  // Dates before the first recorded snapshot are left to exchange rate backfill, so only yesterday is recorded when there is none
+  let first_snapshot = collection
+    .find_one(None, FindOneOptions::builder().sort(doc! { "date": 1 }).build())
+    .await
+    .map_err(|e| JobError::Retryable(format!("failed to get first exchange rate snapshot from mongodb database. {}", e)))?;
+  let first_date = match first_snapshot {
+    Some(snapshot) => OffsetDateTime::from_unix_timestamp(snapshot.date)?.date(),
+    None => yesterday,
+  };
+  let expected_snapshots_count = count_expected_exchange_rate_snapshots(&context.pg_client)
+    .await
+    .map_err(JobError::Retryable)?;
+  let mut missing_dates = vec![];
+  let mut date = yesterday.saturating_sub(Duration::days(catch_up_days as i64 - 1)).max(first_date);
+  while date <= yesterday {
+    if count_exchange_rate_snapshots_of_date(&collection, date).await? < expected_snapshots_count {
+      missing_dates.push(date);
+    }
+    date = date.next_day().unwrap();
+  }
+  if missing_dates.iter().any(|date| *date < yesterday) {
+    info!(
+      "exchange rate snapshots of {} dates in the last {catch_up_days} days are incomplete since {}. going to record them",
+      missing_dates.len(),
+      missing_dates[0]
+    );
+  }
 
-  // Calculate the string and unix format for yesterday
-  let raw_yesterday = OffsetDateTime::now_utc().checked_sub(Duration::days(1)).unwrap();
-  let yesterday = OffsetDateTime::new_utc(
-    Date::from_calendar_date(raw_yesterday.year(), raw_yesterday.month(), raw_yesterday.day()).unwrap(),
-    Time::from_hms_nano(0, 0, 0, 0).unwrap(),
+  // Record the missing dates from the oldest one, a date failing again is retried by the following runs within the window
+  let mut summary = RunSummary::default();
+  for date in missing_dates.into_iter() {
+    summary += record_exchange_rate_snapshots_of_date(&context, &collection, date).await?;
+  }
+  let report = summary.written.unwrap_or_default();
+  info!(
+    "recorded all missing exchange rate snapshots. {} inserted, {} updated, {} unchanged",
+    report.inserted, report.updated, report.unchanged
   );
-  // YYYY-MM-DD format of yesterday
-  let string_format_yesterday = yesterday
-    .format(&format_description::parse("[year]-[month]-[day]").unwrap())
-    .unwrap();
+
+  Ok(summary)
+}
+
+// A date is complete when it has snapshot of every currency pair
+pub(super) async fn count_expected_exchange_rate_snapshots(pg_client: &Pool<Postgres>) -> Result<u64, String> {
+  let currencies_count = get_all_currencies(pg_client).await?.len() as u64;
+  Ok(currencies_count * currencies_count.saturating_sub(1))
+}
+
+pub(super) fn get_exchange_rate_snapshots_collection(mdb_client: &Client) -> Collection<ExchangeRateSnapshot> {
+  mdb_client
+    .database("snapshots")
+    .collection::<ExchangeRateSnapshot>("exchange_rate_snapshots")
+}
+
+#[tracing::instrument(skip(collection))]
+pub(super) async fn count_exchange_rate_snapshots_of_date(
+  collection: &Collection<ExchangeRateSnapshot>,
+  date: Date,
+) -> Result<u64, Box<dyn Error>> {
+  let timestamp = OffsetDateTime::new_utc(date, Time::MIDNIGHT);
+  let count = collection
+    .count_documents(doc! { "date": timestamp.unix_timestamp() }, None)
+    .await
+    .map_err(|e| {
+      JobError::Retryable(format!(
+        "failed to count exchange rate snapshots of {date} in mongodb database. {}",
+        e
+      ))
+    })?;
+
+  Ok(count)
+}
+
+#[tracing::instrument(skip(context, collection))]
+pub(super) async fn record_exchange_rate_snapshots_of_date(
+  context: &JobContext,
+  collection: &Collection<ExchangeRateSnapshot>,
+  date: Date,
+) -> Result<RunSummary, Box<dyn Error>> {
+  // Calculate the string and unix format for the date
+  let timestamp = OffsetDateTime::new_utc(date, Time::MIDNIGHT);
+  // YYYY-MM-DD format of the date
+  let string_format_date = date.format(&format_description::parse("[year]-[month]-[day]").unwrap()).unwrap();
 
   // Fetch and process the exchange rate pairs
-  let records = fetch_and_process_exchange_rates(&pg_client, string_format_yesterday.as_str()).await?;
-  debug!("extracted all exchange rate pairs successfully. going to insert them into database");
+  let (records, mut summary) = fetch_and_process_exchange_rates(context, ExchangeRateDate::Historical(date)).await?;
+  debug!("extracted exchange rate pairs of {string_format_date}. going to upsert them into database");
 
   // Convert exchange rate into mongodb snapshot schema
   let snapshots = records
     .into_iter()
     .map(|r| ExchangeRateSnapshot {
       rate: r.rate,
-      date: yesterday.unix_timestamp(),
+      date: timestamp.unix_timestamp(),
       base_currency_id: r.base_currency_id.clone(),
       target_currency_id: r.target_currency_id.clone(),
-      _id: format!("{}-{}-{}", r.base_currency_id, r.target_currency_id, string_format_yesterday),
+      _id: format!("{}-{}-{}", r.base_currency_id, r.target_currency_id, string_format_date),
+      provider: Some(r.provider),
     })
     .collect::<Vec<ExchangeRateSnapshot>>();
 
-  // Insert snapshots into mongodb database
-  let mdb_client = client::init_mdb().await?;
-  let mdb_snapshots_db = mdb_client.database("snapshots");
-  let collection = mdb_snapshots_db.collection::<ExchangeRateSnapshot>("exchange_rate_snapshots");
-  collection
-    .insert_many(snapshots, None)
-    .await
-    .map_err(|e| format!("failed to insert snapshots into mongodb database. {}", e))?;
-  debug!("successfully inserted all exchange rate snapshots of {string_format_yesterday} into database");
+  // Upsert snapshots into mongodb database so that the same date can be recorded again safely
+  let mut report = WriteReport::default();
+  for snapshot in snapshots.iter() {
+    let result = collection
+      .replace_one(
+        doc! { "_id": &snapshot._id },
+        snapshot,
+        ReplaceOptions::builder().upsert(true).build(),
+      )
+      .await
+      .map_err(|e| JobError::Retryable(format!("failed to upsert snapshot {} into mongodb database. {}", snapshot._id, e)))?;
+    summary.succeed();
+    if result.upserted_id.is_some() {
+      report.inserted += 1;
+    } else if result.modified_count > 0 {
+      report.updated += 1;
+    } else {
+      report.unchanged += 1;
+    }
+  }
+  info!(
+    "recorded exchange rate snapshots of {string_format_date}. {} inserted, {} updated, {} unchanged",
+    report.inserted, report.updated, report.unchanged
+  );
 
-  // End database connection
-  pg_client.close().await;
+  summary.write(report);
 
-  Ok(())
+  // A date without any snapshot is not recorded at all, which should be retried rather than left as a gap
+  summary.into_result()
 }
 
-#[tracing::instrument]
-pub async fn update_latest_exchange_rates() -> Result<(), Box<dyn Error>> {
-  // Setup postgresql database connection
-  let pg_client = client::init_pg().await?;
+#[tracing::instrument(skip(context))]
+pub async fn update_latest_exchange_rates(context: JobContext) -> Result<RunSummary, Box<dyn Error>> {
+  let sanity_check_config = config::load_exchange_rate_sanity_check_config().map_err(JobError::Permanent)?;
 
   // Fetch and process the exchange rate pairs
-  let records = fetch_and_process_exchange_rates(&pg_client, "latest").await?;
-  debug!("extracted all exchange rate pairs successfully. going to insert them into database");
+  let (records, mut summary) = fetch_and_process_exchange_rates(&context, ExchangeRateDate::Latest).await?;
+  let pg_client = context.pg_client;
+  debug!("extracted exchange rate pairs. going to check and upsert them into database");
+
+  // Compare against the stored rates and the inverse rates fetched together
+  let stored_rates = get_all_exchange_rates(&pg_client)
+    .await
+    .map_err(JobError::Retryable)?
+    .into_iter()
+    .filter_map(|r| Some(((r.base_currency_id?.to_string(), r.target_currency_id?.to_string()), r.rate)))
+    .collect::<HashMap<(String, String), String>>();
+  let fetched_rates = records
+    .iter()
+    .map(|r| ((r.base_currency_id.clone(), r.target_currency_id.clone()), r.rate.clone()))
+    .collect::<HashMap<(String, String), String>>();
 
   for record in records.iter() {
-    // Check if there is existing record in database already
-    let is_exchange_rate_record_existed = check_existing_exchange_rate(
-      &pg_client,
-      CheckExistingExchangeRateParams {
-        base_currency_id: Uuid::parse_str(&record.base_currency_id)?,
-        target_currency_id: Uuid::parse_str(&record.target_currency_id)?,
-      },
-    )
-    .await?;
+    let base_currency_id = Uuid::parse_str(&record.base_currency_id)?;
+    let target_currency_id = Uuid::parse_str(&record.target_currency_id)?;
+    let pair = (record.base_currency_id.clone(), record.target_currency_id.clone());
+    let inverse_pair = (record.target_currency_id.clone(), record.base_currency_id.clone());
+    let previous_rate = stored_rates.get(&pair);
 
-    if is_exchange_rate_record_existed {
-      debug!(
-        "going to update exchange rate for pair {}:{}",
-        record.base_currency_id, record.target_currency_id
+    // Hold the suspicious exchange rate for approval instead of writing it
+    let reasons = check_exchange_rate(
+      &sanity_check_config,
+      &record.rate,
+      previous_rate.map(|r| r.as_str()),
+      fetched_rates.get(&inverse_pair).map(|r| r.as_str()),
+    );
+    if !reasons.is_empty() {
+      let reason = reasons.join(". ");
+      summary.skip(
+        &format!("{}:{}", record.base_currency_id, record.target_currency_id),
+        format!("quarantined exchange rate {}. {}", record.rate, reason),
       );
-      update_exchange_rate(
+      quarantine_exchange_rate(
         &pg_client,
-        UpdateExchangeRateParams {
+        QuarantineExchangeRateParams {
+          reason,
+          base_currency_id,
+          target_currency_id,
+          id: Uuid::new_v4(),
           rate: record.rate.clone(),
-          base_currency_id: Uuid::parse_str(&record.base_currency_id)?,
-          target_currency_id: Uuid::parse_str(&record.target_currency_id)?,
+          previous_rate: previous_rate.cloned(),
+          provider: record.provider.clone(),
         },
       )
-      .await?;
-    } else {
-      debug!(
-        "going to create new exchange rate record for pair {}:{}",
-        record.base_currency_id, record.target_currency_id
+      .await
+      .map_err(JobError::Retryable)?;
+      continue;
+    }
+
+    // A pending quarantine of the pair is outdated by the rate passing the checks, so that it cannot be approved over it
+    let mut pg_transaction = pg_client
+      .begin()
+      .await
+      .map_err(|e| JobError::Retryable(format!("failed to begin transaction in postgresql database. {}", e)))?;
+    write_latest_exchange_rate(
+      &mut pg_transaction,
+      base_currency_id,
+      target_currency_id,
+      &record.rate,
+      &record.provider,
+    )
+    .await
+    .map_err(JobError::Retryable)?;
+    let superseded = supersede_pending_exchange_rate_quarantine(
+      &mut *pg_transaction,
+      SupersedePendingExchangeRateQuarantineParams {
+        base_currency_id,
+        target_currency_id,
+      },
+    )
+    .await
+    .map_err(JobError::Retryable)?;
+    pg_transaction.commit().await.map_err(|e| {
+      JobError::Retryable(format!(
+        "failed to commit transaction of exchange rate in postgresql database. {}",
+        e
+      ))
+    })?;
+    if superseded {
+      info!(
+        "superseded pending quarantine of pair {base_currency_id}:{target_currency_id} by exchange rate {}",
+        record.rate
       );
-      create_new_exchange_rate(
-        &pg_client,
-        CreateNewExchangeRateParams {
-          rate: record.rate.clone(),
-          base_currency_id: Uuid::parse_str(&record.base_currency_id)?,
-          target_currency_id: Uuid::parse_str(&record.target_currency_id)?,
-        },
-      )
-      .await?;
     }
+    summary.succeed();
+  }
+  if !summary.skipped.is_empty() {
+    warn!(
+      "quarantined {} of {} exchange rates. they need to be approved through admin API",
+      summary.skipped.len(),
+      records.len()
+    );
   }
 
-  // End database connection
-  pg_client.close().await;
-
-  Ok(())
+  summary.into_result()
 }
 
-#[tracing::instrument]
-pub async fn fetch_and_process_exchange_rates(pg_client: &Pool<Postgres>, date: &str) -> Result<Vec<ExchangeRateRecord>, Box<dyn Error>> {
-  // Get value for environment variable 'EXCHANGE_RATES_API_URL'
-  let exchange_rates_api_url =
-    var("EXCHANGE_RATES_API_URL").map_err(|e| format!("missing config for environment variable EXCHANGE_RATES_API_URL. {}", e))?;
+// Create or update the latest exchange rate of currency pair along with the provider that served it
+#[tracing::instrument(skip(pg_transaction))]
+pub(super) async fn write_latest_exchange_rate(
+  pg_transaction: &mut Transaction<'_, Postgres>,
+  base_currency_id: Uuid,
+  target_currency_id: Uuid,
+  rate: &str,
+  provider: &str,
+) -> Result<(), String> {
+  // Check if there is existing record in database already
+  let is_exchange_rate_record_existed = check_existing_exchange_rate(
+    &mut **pg_transaction,
+    CheckExistingExchangeRateParams {
+      base_currency_id,
+      target_currency_id,
+    },
+  )
+  .await?;
 
+  if is_exchange_rate_record_existed {
+    debug!("going to update exchange rate for pair {base_currency_id}:{target_currency_id}");
+    update_exchange_rate(
+      &mut **pg_transaction,
+      UpdateExchangeRateParams {
+        base_currency_id,
+        target_currency_id,
+        rate: rate.to_string(),
+      },
+    )
+    .await?;
+  } else {
+    debug!("going to create new exchange rate record for pair {base_currency_id}:{target_currency_id}");
+    create_new_exchange_rate(
+      &mut **pg_transaction,
+      CreateNewExchangeRateParams {
+        base_currency_id,
+        target_currency_id,
+        rate: rate.to_string(),
+      },
+    )
+    .await?;
+  }
+
+  // Record which provider served the latest exchange rate
+  upsert_exchange_rate_source(
+    &mut **pg_transaction,
+    UpsertExchangeRateSourceParams {
+      base_currency_id,
+      target_currency_id,
+      provider: provider.to_string(),
+    },
+  )
+  .await
+}
+
+#[tracing::instrument(skip(context))]
+// Pairs which cannot be fetched are reported as failed items so that the other pairs are still processed
+async fn fetch_and_process_exchange_rates(
+  context: &JobContext,
+  date: ExchangeRateDate,
+) -> Result<(Vec<ExchangeRateRecord>, RunSummary), Box<dyn Error>> {
   // Get all supported currencies from postgres database
-  let currencies = get_all_currencies(&pg_client).await?;
+  let currencies = get_all_currencies(&context.pg_client).await.map_err(JobError::Retryable)?;
   debug!("got all supported currencies from database");
 
-  // Try to fetch exchange rates API using each supported currency one by one
+  // Derive all pairs from a single fetch when pivot currency is configured
+  match config::load_exchange_rate_pivot_currency() {
+    Some(pivot_currency) => fetch_and_derive_cross_exchange_rates(context, &currencies, &pivot_currency, date).await,
+    None => fetch_direct_exchange_rates(context, &currencies, date).await,
+  }
+}
+
+#[tracing::instrument(skip(context, currencies))]
+async fn fetch_direct_exchange_rates(
+  context: &JobContext,
+  currencies: &[Currency],
+  date: ExchangeRateDate,
+) -> Result<(Vec<ExchangeRateRecord>, RunSummary), Box<dyn Error>> {
+  // Try to fetch exchange rates using each supported currency one by one
   let mut records: Vec<ExchangeRateRecord> = vec![];
+  let mut summary = RunSummary::default();
 
   for currency in currencies.iter() {
     let base_currency_ticker = currency.ticker.to_lowercase();
     let interested_currencies = currencies.iter().filter(|c| c.id != currency.id).collect::<Vec<&Currency>>();
-    let response = reqwest::get(format!("{exchange_rates_api_url}@{date}/v1/currencies/{base_currency_ticker}.json",))
-      .await
-      .map_err(|e| format!("failed to fetch exchange rates with base currency {base_currency_ticker}. {}", e))?;
+    let target_currency_tickers = interested_currencies
+      .iter()
+      .map(|c| c.ticker.to_lowercase())
+      .collect::<Vec<String>>();
+    let exchange_rates = match get_exchange_rates_with_fallback(
+      &context.exchange_rate_providers,
+      &base_currency_ticker,
+      &target_currency_tickers,
+      date,
+    )
+    .await
+    {
+      Ok(exchange_rates) => exchange_rates,
+      Err(e) => {
+        for target_currency in interested_currencies.iter() {
+          summary.fail(&format!("{}/{}", currency.ticker, target_currency.ticker), &e);
+        }
+        continue;
+      }
+    };
+    debug!("fetched exchange rates with base currency {base_currency_ticker}. going to extract exchange rate pair");
 
-    // Convert raw API response to consumable exchange rates json for processing
-    let exchange_rate_data = response
-      .json::<HashMap<String, Value>>()
-      .await
-      .map_err(|e| format!("failed to fetch exchange rates with base currency {base_currency_ticker}. {}", e))?;
-    debug!("fetched exchange currencies API successfully. going to extract exchange rate pair");
-
-    // Extract exchange rates pair based on target source currency
-    let exchange_rate_list = exchange_rate_data
-      .get(&base_currency_ticker)
-      .ok_or_else(|| format!("exchange rate list does not exist for base currency {base_currency_ticker}"))?
-      .as_object()
-      .unwrap();
-    for target_currency in interested_currencies.iter() {
-      let exchange_rate_value = exchange_rate_list
-        .get(&target_currency.ticker.to_lowercase())
-        .ok_or_else(|| {
-          format!(
-            "exchange rate value does not exist for target currency {}",
-            target_currency.ticker.to_lowercase()
-          )
-        })?
-        .as_f64()
-        .unwrap();
+    for (target_currency, target_currency_ticker) in interested_currencies.iter().zip(target_currency_tickers.iter()) {
+      let Some((provider, exchange_rate_value)) = exchange_rates.get(target_currency_ticker) else {
+        summary.fail(
+          &format!("{}/{}", currency.ticker, target_currency.ticker),
+          format!("exchange rate value does not exist for target currency {target_currency_ticker}"),
+        );
+        continue;
+      };
       records.push(ExchangeRateRecord {
         rate: format!("{:.8}", exchange_rate_value),
         base_currency_id: currency.id.to_string(),
         target_currency_id: target_currency.id.to_string(),
+        provider: provider.to_string(),
+      });
+    }
+  }
+
+  Ok((records, summary))
+}
+
+// Fetch exchange rates once against pivot currency and derive every pair from them as cross rate
+// so that all pairs come from the same point in time, e.g. GBP/JPY = USD/JPY / USD/GBP
+#[tracing::instrument(skip(context, currencies))]
+async fn fetch_and_derive_cross_exchange_rates(
+  context: &JobContext,
+  currencies: &[Currency],
+  pivot_currency: &str,
+  date: ExchangeRateDate,
+) -> Result<(Vec<ExchangeRateRecord>, RunSummary), Box<dyn Error>> {
+  let target_currency_tickers = currencies
+    .iter()
+    .map(|c| c.ticker.to_lowercase())
+    .filter(|ticker| ticker != pivot_currency)
+    .collect::<Vec<String>>();
+  let provider_exchange_rates =
+    get_exchange_rates_of_each_provider(&context.exchange_rate_providers, pivot_currency, &target_currency_tickers, date).await?;
+  debug!("fetched exchange rates with pivot currency {pivot_currency}. going to derive cross rates");
+
+  Ok(derive_cross_exchange_rates(currencies, pivot_currency, &provider_exchange_rates))
+}
+
+// Derive the rate of every pair of currencies from the rates against pivot currency
+// Both legs of a cross rate come from the response of the same provider, as rates of different providers are taken
+// at different points in time. The first provider in priority order serving both of them is used
+fn derive_cross_exchange_rates(
+  currencies: &[Currency],
+  pivot_currency: &str,
+  provider_exchange_rates: &[ProviderExchangeRates],
+) -> (Vec<ExchangeRateRecord>, RunSummary) {
+  // Exchange rate from pivot currency to the currency served by the provider, the rate of pivot currency itself is always one
+  let get_pivot_exchange_rate = |exchange_rates: &HashMap<String, f64>, ticker: &str| match exchange_rates.get(ticker) {
+    Some(exchange_rate_value) => Decimal::from_f64(*exchange_rate_value).filter(|rate| rate.is_sign_positive() && !rate.is_zero()),
+    None if ticker == pivot_currency => Some(Decimal::ONE),
+    None => None,
+  };
+
+  let mut records: Vec<ExchangeRateRecord> = vec![];
+  let mut summary = RunSummary::default();
+  for base_currency in currencies.iter() {
+    let base_ticker = base_currency.ticker.to_lowercase();
+    for target_currency in currencies.iter().filter(|c| c.id != base_currency.id) {
+      let target_ticker = target_currency.ticker.to_lowercase();
+      let cross_exchange_rate = provider_exchange_rates.iter().find_map(|(provider, exchange_rates)| {
+        let base_rate = get_pivot_exchange_rate(exchange_rates, &base_ticker)?;
+        let target_rate = get_pivot_exchange_rate(exchange_rates, &target_ticker)?;
+        target_rate.checked_div(base_rate).map(|rate| (*provider, rate))
       });
+      let Some((provider, rate)) = cross_exchange_rate else {
+        let is_served = |ticker: &str| {
+          provider_exchange_rates
+            .iter()
+            .any(|(_, exchange_rates)| get_pivot_exchange_rate(exchange_rates, ticker).is_some())
+        };
+        let reason = match [&base_ticker, &target_ticker].into_iter().find(|ticker| !is_served(ticker)) {
+          Some(ticker) => format!("valid exchange rate value does not exist for currency {ticker}"),
+          None => {
+            format!("no exchange rate provider serves both {base_ticker} and {target_ticker} against pivot currency {pivot_currency}")
+          }
+        };
+        summary.fail(&format!("{}/{}", base_currency.ticker, target_currency.ticker), reason);
+        continue;
+      };
+      records.push(ExchangeRateRecord {
+        rate: format!("{:.8}", rate.round_dp(8)),
+        base_currency_id: base_currency.id.to_string(),
+        target_currency_id: target_currency.id.to_string(),
+        provider: provider.to_string(),
+      });
+    }
+  }
+
+  (records, summary)
+}
+
+// Ask exchange rate providers in priority order for all the target currencies until every one of them is served,
+// keeping the response of each provider apart so that rates taken at different points in time are not mixed
+#[tracing::instrument(skip(providers))]
+async fn get_exchange_rates_of_each_provider(
+  providers: &ExchangeRateProviders,
+  base_currency: &str,
+  target_currencies: &[String],
+  date: ExchangeRateDate,
+) -> Result<Vec<ProviderExchangeRates>, JobError> {
+  let mut errors = vec![];
+  let mut provider_exchange_rates: Vec<ProviderExchangeRates> = vec![];
+  for provider in providers.iter() {
+    let missing_currencies_count = target_currencies
+      .iter()
+      .filter(|c| {
+        !provider_exchange_rates
+          .iter()
+          .any(|(_, exchange_rates)| exchange_rates.contains_key(*c))
+      })
+      .count();
+    if missing_currencies_count == 0 {
+      break;
+    }
+    if !provider_exchange_rates.is_empty() {
+      warn!(
+        "{missing_currencies_count} target currencies are missing. going to try exchange rate provider {}",
+        provider.name()
+      );
+    }
+    match provider.get_exchange_rates(base_currency, target_currencies, date).await {
+      Ok(exchange_rates) => provider_exchange_rates.push((provider.name(), exchange_rates)),
+      Err(e) => {
+        warn!(
+          "exchange rate provider {} failed. going to try next provider. {}",
+          provider.name(),
+          e
+        );
+        errors.push(format!("{}: {}", provider.name(), e));
+      }
+    }
+  }
+
+  if provider_exchange_rates.is_empty() && !errors.is_empty() {
+    return Err(JobError::Retryable(format!(
+      "all exchange rate providers failed to serve {date} rates with base currency {base_currency}. {}",
+      errors.join(". ")
+    )));
+  }
+
+  Ok(provider_exchange_rates)
+}
+
+// Try exchange rate providers in priority order, asking each of them only for the target currencies
+// not served by the previous ones, e.g. currencies missing at the first provider are filled from the next one
+// Returns exchange rates keyed by target currency along with the provider that served each of them
+#[tracing::instrument(skip(providers))]
+async fn get_exchange_rates_with_fallback(
+  providers: &ExchangeRateProviders,
+  base_currency: &str,
+  target_currencies: &[String],
+  date: ExchangeRateDate,
+) -> Result<HashMap<String, (&'static str, f64)>, JobError> {
+  let mut errors = vec![];
+  let mut exchange_rates: HashMap<String, (&'static str, f64)> = HashMap::new();
+  for provider in providers.iter() {
+    let missing_currencies = target_currencies
+      .iter()
+      .filter(|c| !exchange_rates.contains_key(*c))
+      .cloned()
+      .collect::<Vec<String>>();
+    if missing_currencies.is_empty() {
+      break;
+    }
+    match provider.get_exchange_rates(base_currency, &missing_currencies, date).await {
+      Ok(provider_exchange_rates) => {
+        let served = missing_currencies
+          .into_iter()
+          .filter_map(|c| provider_exchange_rates.get(&c).map(|rate| (c, (provider.name(), *rate))))
+          .collect::<Vec<_>>();
+        if served.len() < target_currencies.len() - exchange_rates.len() {
+          warn!(
+            "exchange rate provider {} served {} of {} missing target currencies. going to try next provider",
+            provider.name(),
+            served.len(),
+            target_currencies.len() - exchange_rates.len()
+          );
+        }
+        exchange_rates.extend(served);
+      }
+      Err(e) => {
+        warn!(
+          "exchange rate provider {} failed. going to try next provider. {}",
+          provider.name(),
+          e
+        );
+        errors.push(format!("{}: {}", provider.name(), e));
+      }
+    }
+  }
+
+  if exchange_rates.is_empty() && !errors.is_empty() {
+    return Err(JobError::Retryable(format!(
+      "all exchange rate providers failed to serve {date} rates with base currency {base_currency}. {}",
+      errors.join(". ")
+    )));
+  }
+
+  Ok(exchange_rates)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn currency(ticker: &str) -> Currency {
+    Currency {
+      id: Uuid::new_v4(),
+      ticker: ticker.to_string(),
+      symbol: String::new(),
     }
   }
 
-  Ok(records)
+  fn rates(entries: &[(&str, f64)]) -> HashMap<String, f64> {
+    entries.iter().map(|(ticker, rate)| (ticker.to_string(), *rate)).collect()
+  }
+
+  fn find<'a>(records: &'a [ExchangeRateRecord], base: &Currency, target: &Currency) -> Option<&'a ExchangeRateRecord> {
+    records
+      .iter()
+      .find(|r| r.base_currency_id == base.id.to_string() && r.target_currency_id == target.id.to_string())
+  }
+
+  #[test]
+  fn derives_every_pair_from_one_provider() {
+    let (usd, gbp, jpy) = (currency("USD"), currency("GBP"), currency("JPY"));
+    let currencies = [usd, gbp, jpy];
+    let provider_exchange_rates = [("fawazahmed0", rates(&[("gbp", 0.8), ("jpy", 150.0)]))];
+
+    let (records, summary) = derive_cross_exchange_rates(&currencies, "usd", &provider_exchange_rates);
+
+    assert_eq!(records.len(), 6);
+    assert!(summary.failed.is_empty());
+    let [usd, gbp, jpy] = &currencies;
+    assert_eq!(find(&records, usd, gbp).unwrap().rate, "0.80000000");
+    assert_eq!(find(&records, gbp, usd).unwrap().rate, "1.25000000");
+    assert_eq!(find(&records, gbp, jpy).unwrap().rate, "187.50000000");
+    assert!(records.iter().all(|r| r.provider == "fawazahmed0"));
+  }
+
+  #[test]
+  fn never_mixes_legs_of_different_providers() {
+    let currencies = [currency("USD"), currency("GBP"), currency("JPY")];
+    let provider_exchange_rates = [("fawazahmed0", rates(&[("gbp", 0.8)])), ("ecb", rates(&[("jpy", 150.0)]))];
+
+    let (records, summary) = derive_cross_exchange_rates(&currencies, "usd", &provider_exchange_rates);
+
+    let [usd, gbp, jpy] = &currencies;
+    assert_eq!(find(&records, usd, gbp).unwrap().provider, "fawazahmed0");
+    assert_eq!(find(&records, usd, jpy).unwrap().provider, "ecb");
+    assert!(find(&records, gbp, jpy).is_none());
+    assert!(find(&records, jpy, gbp).is_none());
+    assert_eq!(summary.failed.len(), 2);
+    assert!(
+      summary.failed[0].contains("no exchange rate provider serves both"),
+      "{:?}",
+      summary.failed
+    );
+  }
+
+  #[test]
+  fn takes_both_legs_from_next_provider_serving_them() {
+    let currencies = [currency("USD"), currency("GBP"), currency("JPY")];
+    let provider_exchange_rates = [
+      ("fawazahmed0", rates(&[("gbp", 0.8)])),
+      ("ecb", rates(&[("gbp", 0.75), ("jpy", 150.0)])),
+    ];
+
+    let (records, summary) = derive_cross_exchange_rates(&currencies, "usd", &provider_exchange_rates);
+
+    let [usd, gbp, jpy] = &currencies;
+    assert!(summary.failed.is_empty());
+    assert_eq!(find(&records, usd, gbp).unwrap().provider, "fawazahmed0");
+    let gbp_jpy = find(&records, gbp, jpy).unwrap();
+    assert_eq!((gbp_jpy.provider.as_str(), gbp_jpy.rate.as_str()), ("ecb", "200.00000000"));
+  }
+
+  #[test]
+  fn fails_pairs_of_currency_without_valid_rate() {
+    let currencies = [currency("USD"), currency("GBP"), currency("JPY")];
+    let provider_exchange_rates = [("fawazahmed0", rates(&[("gbp", 0.8), ("jpy", 0.0)]))];
+
+    let (records, summary) = derive_cross_exchange_rates(&currencies, "usd", &provider_exchange_rates);
+
+    assert_eq!(records.len(), 2);
+    assert_eq!(summary.failed.len(), 4);
+    assert!(summary
+      .failed
+      .iter()
+      .all(|f| f.contains("valid exchange rate value does not exist for currency jpy")));
+  }
 }
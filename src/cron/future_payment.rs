@@ -1,38 +1,67 @@
-use crate::external::db::client;
-use crate::external::db::query::account::{get_account_balance_by_id, update_account_balance, UpdateAccountBalanceParams};
+use super::recurrence::Recurrence;
+use super::retry::JobError;
+use super::summary::RunSummary;
+use super::JobContext;
+use crate::config;
+use crate::external::db::query::account::{get_account_balance_by_id_for_update, update_account_balance, UpdateAccountBalanceParams};
 use crate::external::db::query::future_payment::{
-  delete_future_payment, get_all_future_payments, update_future_payment_schedule, UpdateFuturePaymentScheduleParams,
+  delete_future_payment, get_all_future_payments, update_future_payment_schedule, FuturePayment, UpdateFuturePaymentScheduleParams,
+};
+use crate::external::db::query::future_payment_catch_up::{upsert_future_payment_catch_up, UpsertFuturePaymentCatchUpParams};
+use crate::external::db::query::future_payment_recurrence::{
+  get_future_payment_recurrence, upsert_future_payment_recurrence, UpsertFuturePaymentRecurrenceParams,
+};
+use crate::external::db::query::future_payment_transaction::{
+  create_new_future_payment_transaction, CreateNewFuturePaymentTransactionParams,
 };
 use crate::external::db::query::transaction::{create_new_transaction, CreateNewTransactionParams};
 use crate::utils::format_timestamp;
 use rust_decimal::Decimal;
+use sqlx::{Pool, Postgres};
+use std::collections::HashMap;
 use std::error::Error;
 use std::str::FromStr;
-use time::{Date, Duration, OffsetDateTime, Time};
-use tracing::debug;
+use time::{Date, OffsetDateTime, Time};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+// Overdue occurrence of a future payment to be settled
+#[derive(Debug)]
+struct FuturePaymentOccurrence<'a> {
+  future_payment: &'a FuturePayment,
+  scheduled_at: OffsetDateTime,
+  // Schedule of the following occurrence, which is none for one-off payment
+  next_scheduled_at: Option<OffsetDateTime>,
+}
 
-#[tracing::instrument]
-pub async fn monitor_future_payments() -> Result<(), Box<dyn Error>> {
-  // Setup postgresql database connection
-  let pg_client = client::init_pg().await?;
+// Occurrences of a future payment settled in one execution, which is audited once there is more than one of them
+#[derive(Debug)]
+struct FuturePaymentCatchUp {
+  id: Uuid,
+  capped: bool,
+  occurrences: i32,
+  first_occurrence_at: Option<OffsetDateTime>,
+  last_occurrence_at: Option<OffsetDateTime>,
+}
+
+// A future payment failing to be settled, e.g. with an invalid frequency or amount, is reported as failed item
+// and left to the following executions, so that the other future payments are still settled
+#[tracing::instrument(skip(context))]
+pub async fn monitor_future_payments(context: JobContext) -> Result<RunSummary, Box<dyn Error>> {
+  let pg_client = context.pg_client;
+  let max_catch_up_occurrences = config::load_future_payment_max_catch_up_occurrences().map_err(JobError::Permanent)?;
 
   // Get all future payments of all users in database
-  let future_payments = get_all_future_payments(&pg_client).await?;
+  let future_payments = get_all_future_payments(&pg_client).await.map_err(JobError::Retryable)?;
   debug!("got all future payments from postgresql database");
 
+  // Work out every overdue occurrence of all future payments, e.g. those missed while the service was down
+  let now = OffsetDateTime::now_utc();
+  let mut summary = RunSummary::default();
+  let mut occurrences = vec![];
+  let mut catch_ups = HashMap::new();
   for future_payment in future_payments.iter() {
-    let today = OffsetDateTime::now_utc();
-    let start_of_scheduled_at_date = OffsetDateTime::new_utc(
-      Date::from_calendar_date(
-        future_payment.scheduled_at.year(),
-        future_payment.scheduled_at.month(),
-        future_payment.scheduled_at.day(),
-      )
-      .unwrap(),
-      Time::from_hms_nano(0, 0, 0, 0).unwrap(),
-    );
-
-    if future_payment.scheduled_at.gt(&today) {
+    if future_payment.scheduled_at.gt(&now) {
       debug!(
         "future payment {}({}) will not be processed now as the next schedule is {}",
         future_payment.name,
@@ -42,20 +71,209 @@ pub async fn monitor_future_payments() -> Result<(), Box<dyn Error>> {
       continue;
     }
 
-    // The scheduled date for future payment has fallen behind current timestamp
-    // So will process the payment
-    debug!(
-      "going to process future payment {}({}) of amount {} for account {}",
-      future_payment.name, future_payment.id, future_payment.amount, future_payment.account_id
+    let (future_payment_occurrences, capped) =
+      match get_overdue_occurrences(&pg_client, future_payment, now, max_catch_up_occurrences).await {
+        Ok(overdue_occurrences) => overdue_occurrences,
+        Err(e) => {
+          summary.fail(&format!("{}({})", future_payment.name, future_payment.id), e);
+          continue;
+        }
+      };
+    if capped {
+      warn!(
+        "future payment {}({}) has more than {max_catch_up_occurrences} overdue occurrences. the rest will be settled in the following executions",
+        future_payment.name, future_payment.id
+      );
+    }
+    catch_ups.insert(
+      future_payment.id,
+      FuturePaymentCatchUp {
+        capped,
+        id: Uuid::new_v4(),
+        occurrences: 0,
+        first_occurrence_at: None,
+        last_occurrence_at: None,
+      },
     );
-    let original_account_balance = get_account_balance_by_id(&pg_client, future_payment.account_id).await?;
+    occurrences.extend(future_payment_occurrences);
+  }
+
+  // Settle the occurrences of all future payments in chronological order so that transactions are created in the order they happened
+  occurrences.sort_by_key(|o| o.scheduled_at);
+  for occurrence in occurrences.iter() {
+    let future_payment = occurrence.future_payment;
+    // The later occurrences of a failed future payment are left to the following executions
+    let Some(catch_up) = catch_ups.get_mut(&future_payment.id) else {
+      continue;
+    };
+    let item = format!("{}({})", future_payment.name, future_payment.id);
+    match settle_future_payment_occurrence(&pg_client, occurrence, catch_up).await {
+      Ok(true) => summary.succeed(),
+      // Only the occurrences actually settled by this execution are counted
+      Ok(false) => summary.skip(
+        &item,
+        format!(
+          "occurrence scheduled at {} has already been settled",
+          format_timestamp(occurrence.scheduled_at)?
+        ),
+      ),
+      Err(e) => {
+        summary.fail(&item, e);
+        catch_ups.remove(&future_payment.id);
+      }
+    }
+  }
+
+  // Catch ups have been audited along with their occurrences
+  for (future_payment_id, catch_up) in catch_ups.iter().filter(|(_, c)| c.occurrences > 1) {
+    if let (Some(first_occurrence_at), Some(last_occurrence_at)) = (catch_up.first_occurrence_at, catch_up.last_occurrence_at) {
+      info!(
+        "caught up on {} overdue occurrences of future payment {future_payment_id} from {} to {}",
+        catch_up.occurrences,
+        format_timestamp(first_occurrence_at)?,
+        format_timestamp(last_occurrence_at)?
+      );
+    }
+  }
+
+  summary.into_result()
+}
+
+// Overdue occurrences of the future payment in chronological order, at most the given number of them
+// Also tells whether there are more overdue occurrences than that
+#[tracing::instrument(skip(pg_client))]
+async fn get_overdue_occurrences<'a>(
+  pg_client: &Pool<Postgres>,
+  future_payment: &'a FuturePayment,
+  now: OffsetDateTime,
+  max_occurrences: usize,
+) -> Result<(Vec<FuturePaymentOccurrence<'a>>, bool), Box<dyn Error>> {
+  // One-off payment only has one occurrence
+  if !future_payment.rolling {
+    let occurrence = FuturePaymentOccurrence {
+      future_payment,
+      scheduled_at: future_payment.scheduled_at,
+      next_scheduled_at: None,
+    };
+    return Ok((vec![occurrence], false));
+  }
+
+  let frequency = future_payment
+    .frequency
+    .ok_or_else(|| format!("rolling future payment {} does not have frequency", future_payment.id))?;
+  let recurrence = Recurrence::from_frequency(frequency)
+    .map_err(|e| format!("failed to get recurrence of future payment {}. {}", future_payment.id, e))?;
+
+  // Count occurrences from the anchor of the schedule, which is started over when the payment is seen for the first time
+  // or its schedule has been changed since, i.e. its frequency is different or it is no longer on the schedule
+  let anchored_at = match get_future_payment_recurrence(pg_client, future_payment.id)
+    .await
+    .map_err(JobError::Retryable)?
+  {
+    Some(r) if r.frequency == frequency && recurrence.is_occurrence(r.anchored_at, future_payment.scheduled_at) => r.anchored_at,
+    _ => {
+      debug!(
+        "going to anchor schedule of future payment {}({}) at {} repeating {recurrence}",
+        future_payment.name,
+        future_payment.id,
+        format_timestamp(future_payment.scheduled_at)?
+      );
+      upsert_future_payment_recurrence(
+        pg_client,
+        UpsertFuturePaymentRecurrenceParams {
+          frequency,
+          future_payment_id: future_payment.id,
+          anchored_at: future_payment.scheduled_at,
+        },
+      )
+      .await
+      .map_err(JobError::Retryable)?;
+      future_payment.scheduled_at
+    }
+  };
+
+  let mut occurrences = vec![];
+  let mut scheduled_at = future_payment.scheduled_at;
+  while scheduled_at <= now {
+    if occurrences.len() >= max_occurrences {
+      return Ok((occurrences, true));
+    }
+    let next_scheduled_at = recurrence
+      .next_occurrence(anchored_at, scheduled_at)
+      .ok_or_else(|| format!("failed to get next schedule of future payment {}", future_payment.id))?;
+    occurrences.push(FuturePaymentOccurrence {
+      future_payment,
+      scheduled_at,
+      next_scheduled_at: Some(next_scheduled_at),
+    });
+    scheduled_at = next_scheduled_at;
+  }
+
+  Ok((occurrences, false))
+}
+
+// Update account balance and create transaction for the occurrence, then move the future payment to its next schedule
+// All steps are done in one database transaction so that they are either all committed or all rolled back,
+// including the audit of the catch up once more than one occurrence of the future payment has been settled
+// Returns whether the occurrence has been settled by this call rather than before
+#[tracing::instrument(skip(pg_client))]
+async fn settle_future_payment_occurrence(
+  pg_client: &Pool<Postgres>,
+  occurrence: &FuturePaymentOccurrence<'_>,
+  catch_up: &mut FuturePaymentCatchUp,
+) -> Result<bool, Box<dyn Error>> {
+  let future_payment = occurrence.future_payment;
+  let start_of_scheduled_at_date = OffsetDateTime::new_utc(
+    Date::from_calendar_date(
+      occurrence.scheduled_at.year(),
+      occurrence.scheduled_at.month(),
+      occurrence.scheduled_at.day(),
+    )
+    .unwrap(),
+    Time::from_hms_nano(0, 0, 0, 0).unwrap(),
+  );
+
+  // The scheduled date for future payment has fallen behind current timestamp
+  // So will process the payment
+  debug!(
+    "going to process future payment {}({}) of amount {} for account {} scheduled at {}",
+    future_payment.name,
+    future_payment.id,
+    future_payment.amount,
+    future_payment.account_id,
+    format_timestamp(occurrence.scheduled_at)?
+  );
+  let mut pg_transaction = pg_client
+    .begin()
+    .await
+    .map_err(|e| JobError::Retryable(format!("failed to begin transaction in postgresql database. {}", e)))?;
+
+  // Claim the idempotency key of the occurrence first, so that reprocessing an occurrence which has been settled,
+  // e.g. by a retry or a duplicate run, does not change the balance or create the transaction again
+  let transaction_id = Uuid::new_v4();
+  let is_occurrence_claimed = create_new_future_payment_transaction(
+    &mut *pg_transaction,
+    CreateNewFuturePaymentTransactionParams {
+      transaction_id,
+      future_payment_id: future_payment.id,
+      occurrence_date: start_of_scheduled_at_date.date(),
+    },
+  )
+  .await
+  .map_err(JobError::Retryable)?;
+
+  if is_occurrence_claimed {
+    // Lock the account before reading its balance so that a concurrent change to it, e.g. from the backend, is not overwritten
+    let original_account_balance = get_account_balance_by_id_for_update(&mut pg_transaction, future_payment.account_id)
+      .await
+      .map_err(JobError::Retryable)?;
     let original_account_balance_decimal =
       Decimal::from_str(&original_account_balance).map_err(|e| format!("failed to parse original account balance into decimal. {}", e))?;
     let payment_amount_decimal =
       Decimal::from_str(&future_payment.amount).map_err(|e| format!("failed to parse future payment amount into decimal. {}", e))?;
 
     // Calculate the final account balance after spending / receiving the payment amount
-    let mut final_account_balance = original_account_balance_decimal.clone();
+    let mut final_account_balance = original_account_balance_decimal;
     if future_payment.income {
       final_account_balance += payment_amount_decimal;
     } else {
@@ -68,18 +286,20 @@ pub async fn monitor_future_payments() -> Result<(), Box<dyn Error>> {
 
     // Update account balance after spending / receiving scheduled payment
     update_account_balance(
-      &pg_client,
+      &mut *pg_transaction,
       UpdateAccountBalanceParams {
         id: future_payment.account_id,
         balance: format!("{:.2}", final_account_balance),
       },
     )
-    .await?;
+    .await
+    .map_err(JobError::Retryable)?;
 
     // Create a new transaction record according to the payment details
     create_new_transaction(
-      &pg_client,
+      &mut *pg_transaction,
       CreateNewTransactionParams {
+        id: transaction_id,
         income: future_payment.income,
         name: future_payment.name.clone(),
         client_id: future_payment.client_id,
@@ -91,49 +311,73 @@ pub async fn monitor_future_payments() -> Result<(), Box<dyn Error>> {
         category: future_payment.category.clone(),
       },
     )
-    .await?;
+    .await
+    .map_err(JobError::Retryable)?;
+  } else {
+    warn!(
+      "occurrence of future payment {}({}) scheduled at {} has already been settled",
+      future_payment.name,
+      future_payment.id,
+      format_timestamp(occurrence.scheduled_at)?
+    );
+  }
 
+  match occurrence.next_scheduled_at {
     // Update next schedule date according to frequency if payment is on rolling basis
-    if future_payment.rolling {
-      let mut next_schedule_date = future_payment.scheduled_at.clone();
-      let frequency = future_payment.frequency.unwrap();
-      let days_to_add = frequency / 86400;
-
-      if days_to_add >= 29 {
-        let months_to_add = days_to_add / 30;
-        let mut final_month = next_schedule_date.month().nth_next(months_to_add.try_into().unwrap());
-        if months_to_add == 0 {
-          final_month = final_month.next();
-        }
-        next_schedule_date = next_schedule_date.replace_month(final_month).unwrap();
-      } else {
-        next_schedule_date = next_schedule_date.replace_date(next_schedule_date.date().checked_add(Duration::days(days_to_add)).unwrap());
-      }
+    Some(next_scheduled_at) => {
       debug!(
         "going to update next schedule for future payment {}({}) to {}",
         future_payment.name,
         future_payment.id,
-        format_timestamp(next_schedule_date)?
+        format_timestamp(next_scheduled_at)?
       );
-
       update_future_payment_schedule(
-        &pg_client,
+        &mut *pg_transaction,
         UpdateFuturePaymentScheduleParams {
           id: future_payment.id,
-          scheduled_at: next_schedule_date,
+          scheduled_at: next_scheduled_at,
         },
       )
-      .await?;
-    } else {
-      // Delete future payment as it is not rolling, i.e. one-off payment
-      delete_future_payment(&pg_client, future_payment.id).await?;
+      .await
+      .map_err(JobError::Retryable)?;
     }
+    // Delete future payment as it is not rolling, i.e. one-off payment
+    None => delete_future_payment(&mut *pg_transaction, future_payment.id)
+      .await
+      .map_err(JobError::Retryable)?,
+  }
 
-    debug!("finished processing future payment {}({})", future_payment.name, future_payment.id,);
+  // Keep the audit in line with the settled occurrences, so that it is right even if a later occurrence fails
+  let first_occurrence_at = catch_up.first_occurrence_at.unwrap_or(occurrence.scheduled_at);
+  let settled_occurrences = catch_up.occurrences + 1;
+  if is_occurrence_claimed && settled_occurrences > 1 {
+    upsert_future_payment_catch_up(
+      &mut *pg_transaction,
+      UpsertFuturePaymentCatchUpParams {
+        first_occurrence_at,
+        id: catch_up.id,
+        future_payment_id: future_payment.id,
+        occurrences: settled_occurrences,
+        last_occurrence_at: occurrence.scheduled_at,
+        capped: catch_up.capped,
+      },
+    )
+    .await
+    .map_err(JobError::Retryable)?;
   }
 
-  // End database connection
-  pg_client.close().await;
+  pg_transaction.commit().await.map_err(|e| {
+    JobError::Retryable(format!(
+      "failed to commit transaction of future payment {} in postgresql database. {}",
+      future_payment.id, e
+    ))
+  })?;
+  if is_occurrence_claimed {
+    catch_up.occurrences = settled_occurrences;
+    catch_up.first_occurrence_at = Some(first_occurrence_at);
+    catch_up.last_occurrence_at = Some(occurrence.scheduled_at);
+  }
+  debug!("finished processing future payment {}({})", future_payment.name, future_payment.id);
 
-  Ok(())
+  Ok(is_occurrence_claimed)
 }
@@ -0,0 +1,187 @@
+use super::lock::ClusterLock;
+use super::summary::RunSummary;
+use crate::external::db::query::job_run::{
+  create_new_job_run, delete_job_run, finish_job_run, get_instances_with_running_job_runs, interrupt_running_job_runs_of_instance,
+  CreateNewJobRunParams, FinishJobRunParams, InterruptRunningJobRunsParams,
+};
+use chrono::{TimeZone, Utc};
+use cron::Schedule;
+use sqlx::{Pool, Postgres};
+use std::fmt;
+use std::str::FromStr;
+use time::OffsetDateTime;
+use tracing::{error, warn};
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobRunStatus {
+  Running,
+  Succeeded,
+  // Some items failed while the rest of them succeeded
+  PartiallySucceeded,
+  Failed,
+  Skipped,
+  TimedOut,
+  // Stopped by shutdown before finishing, which can be resumed later,
+  // or left running by an instance which stopped without recording the outcome, e.g. as it was killed
+  Interrupted,
+}
+
+impl fmt::Display for JobRunStatus {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let status = match self {
+      JobRunStatus::Running => "running",
+      JobRunStatus::Succeeded => "succeeded",
+      JobRunStatus::PartiallySucceeded => "partially_succeeded",
+      JobRunStatus::Failed => "failed",
+      JobRunStatus::Skipped => "skipped",
+      JobRunStatus::TimedOut => "timed_out",
+      JobRunStatus::Interrupted => "interrupted",
+    };
+    write!(f, "{status}")
+  }
+}
+
+#[derive(Debug)]
+pub enum JobRunOutcome {
+  Succeeded(RunSummary),
+  Failed(String),
+  Skipped(String),
+  TimedOut(String),
+}
+
+#[derive(Debug)]
+pub struct JobRun {
+  pub id: Uuid,
+  pub name: &'static str,
+  pub started_at: OffsetDateTime,
+}
+
+// Work out which tick of the cron schedule triggered the current execution
+// Falls back to the actual start time when there is no schedule or it cannot be parsed
+pub fn get_scheduled_tick(schedule: Option<&str>, started_at: OffsetDateTime) -> OffsetDateTime {
+  let upper_bound = Utc.timestamp_opt(started_at.unix_timestamp() + 1, 0).single();
+  schedule
+    .and_then(|schedule| Schedule::from_str(schedule).ok())
+    .zip(upper_bound)
+    .and_then(|(schedule, upper_bound)| schedule.after(&upper_bound).next_back())
+    .and_then(|tick| OffsetDateTime::from_unix_timestamp(tick.timestamp()).ok())
+    .unwrap_or(started_at)
+}
+
+// Insert a new job run record marked as running
+// Failing to record history should never stop the job itself from running
+#[tracing::instrument(skip(pg_client))]
+pub async fn start_job_run(pg_client: &Pool<Postgres>, instance_id: Uuid, name: &'static str, schedule: Option<&str>) -> JobRun {
+  let run = JobRun {
+    name,
+    id: Uuid::new_v4(),
+    started_at: OffsetDateTime::now_utc(),
+  };
+  if let Err(e) = create_new_job_run(
+    pg_client,
+    CreateNewJobRunParams {
+      instance_id,
+      id: run.id,
+      job_name: name.to_string(),
+      status: JobRunStatus::Running.to_string(),
+      scheduled_at: get_scheduled_tick(schedule, run.started_at),
+      started_at: run.started_at,
+    },
+  )
+  .await
+  {
+    error!("failed to record start of cronjob {name} run {}. {}", run.id, e);
+  }
+  run
+}
+
+// Update the job run record with the outcome of the execution
+#[tracing::instrument(skip(pg_client))]
+pub async fn finish_job_run_with_outcome(pg_client: &Pool<Postgres>, run: &JobRun, outcome: &JobRunOutcome, attempts: u32) {
+  let finished_at = OffsetDateTime::now_utc();
+  let (status, error, summary) = match outcome {
+    JobRunOutcome::Succeeded(summary) if !summary.failed.is_empty() => (JobRunStatus::PartiallySucceeded, None, Some(summary)),
+    JobRunOutcome::Succeeded(summary) => (JobRunStatus::Succeeded, None, Some(summary)),
+    JobRunOutcome::Failed(e) => (JobRunStatus::Failed, Some(e.clone()), None),
+    JobRunOutcome::Skipped(reason) => (JobRunStatus::Skipped, Some(reason.clone()), None),
+    JobRunOutcome::TimedOut(e) => (JobRunStatus::TimedOut, Some(e.clone()), None),
+  };
+  if let Err(e) = finish_job_run(
+    pg_client,
+    FinishJobRunParams {
+      error,
+      finished_at,
+      attempts: attempts as i32,
+      id: run.id,
+      items_processed: summary.map(|s| s.succeeded),
+      failed_items: summary.map(|s| s.failed.clone()).unwrap_or_default(),
+      skipped_items: summary.map(|s| s.skipped.clone()).unwrap_or_default(),
+      inserted_items: summary.and_then(|s| s.written).map(|w| w.inserted),
+      updated_items: summary.and_then(|s| s.written).map(|w| w.updated),
+      unchanged_items: summary.and_then(|s| s.written).map(|w| w.unchanged),
+      status: status.to_string(),
+      duration_ms: (finished_at - run.started_at).whole_milliseconds() as i64,
+    },
+  )
+  .await
+  {
+    error!("failed to record outcome of cronjob {} run {}. {}", run.name, run.id, e);
+  }
+}
+
+// Forget the run of a task which found nothing to do, e.g. market is closed, so that history does not get a row on every tick
+#[tracing::instrument(skip(pg_client))]
+pub async fn discard_job_run(pg_client: &Pool<Postgres>, run: &JobRun) {
+  if let Err(e) = delete_job_run(pg_client, run.id).await {
+    error!("failed to discard cronjob {} run {}. {}", run.name, run.id, e);
+  }
+}
+
+// Record a tick that has been skipped without executing the cronjob task
+#[tracing::instrument(skip(pg_client))]
+pub async fn skip_job_run(pg_client: &Pool<Postgres>, instance_id: Uuid, name: &'static str, schedule: Option<&str>, reason: &str) {
+  let run = start_job_run(pg_client, instance_id, name, schedule).await;
+  finish_job_run_with_outcome(pg_client, &run, &JobRunOutcome::Skipped(reason.to_string()), 0).await;
+}
+
+// Every instance holds the lock of its own id as long as it is alive, so that the others can tell whether it is gone
+pub fn get_instance_lock_key(instance_id: Uuid) -> String {
+  format!("everytrack_cron:instance:{instance_id}")
+}
+
+// Mark the job runs left running by instances which are gone as interrupted, as their outcome will never be recorded
+// Runs recorded before instances were tracked have no instance and are always left behind by a previous process
+#[tracing::instrument(skip(pg_client))]
+pub async fn interrupt_orphaned_job_runs(pg_client: &Pool<Postgres>) -> Result<(), String> {
+  for instance_id in get_instances_with_running_job_runs(pg_client).await? {
+    let instance_lock = match instance_id {
+      Some(instance_id) => match ClusterLock::acquire(pg_client, &get_instance_lock_key(instance_id)).await? {
+        Some(instance_lock) => Some(instance_lock),
+        // Instance is still alive and running its cronjobs
+        None => continue,
+      },
+      None => None,
+    };
+    let interrupted = interrupt_running_job_runs_of_instance(
+      pg_client,
+      InterruptRunningJobRunsParams {
+        instance_id,
+        status: JobRunStatus::Interrupted.to_string(),
+        error: "interrupted as the instance running it stopped before it finished".to_string(),
+      },
+    )
+    .await;
+    if let Some(instance_lock) = instance_lock {
+      instance_lock.release().await;
+    }
+    let interrupted = interrupted?;
+    if interrupted > 0 {
+      let instance = instance_id.map_or("of unknown instances".to_string(), |instance_id| {
+        format!("by stopped instance {instance_id}")
+      });
+      warn!("marked {interrupted} job runs left running {instance} as interrupted");
+    }
+  }
+  Ok(())
+}
@@ -0,0 +1,65 @@
+use crate::external::db::query::lock::{is_advisory_lock_held, try_acquire_advisory_lock};
+use sqlx::{Connection, PgConnection, Pool, Postgres};
+use std::time::Duration;
+use tokio::time::{sleep, timeout};
+use tracing::debug;
+
+// How often a held lock is checked, so that an execution which lost its lock is aborted within this interval
+const LOCK_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(15);
+const LOCK_HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Cluster-wide lock taken as session level advisory lock on a dedicated connection, which is neither killed
+// for being idle in transaction nor occupies a connection of the pool for the whole execution
+// The lock is released when the connection is closed, including when the holder crashed
+pub struct ClusterLock {
+  key: String,
+  connection: PgConnection,
+}
+
+impl ClusterLock {
+  // Returns none if the lock is held elsewhere
+  #[tracing::instrument(skip(pg_client))]
+  pub async fn acquire(pg_client: &Pool<Postgres>, key: &str) -> Result<Option<ClusterLock>, String> {
+    let mut connection = PgConnection::connect_with(&pg_client.connect_options())
+      .await
+      .map_err(|e| format!("failed to connect to postgresql database for lock {key}. {}", e))?;
+    if !try_acquire_advisory_lock(&mut connection, key).await? {
+      let _ = connection.close().await;
+      return Ok(None);
+    }
+    debug!("acquired lock {key}");
+
+    Ok(Some(ClusterLock {
+      connection,
+      key: key.to_string(),
+    }))
+  }
+
+  // Resolve with the reason once the lock is lost, e.g. the connection has been dropped or its session killed,
+  // after which another instance may take the lock and the work guarded by it should stop
+  pub async fn lost(&mut self) -> String {
+    loop {
+      sleep(LOCK_HEARTBEAT_INTERVAL).await;
+      match timeout(LOCK_HEARTBEAT_TIMEOUT, is_advisory_lock_held(&mut self.connection, &self.key)).await {
+        Ok(Ok(true)) => continue,
+        Ok(Ok(false)) => return format!("lock {} is no longer held", self.key),
+        Ok(Err(e)) => return format!("lock {} is lost. {}", self.key, e),
+        Err(_) => {
+          return format!(
+            "lock {} is lost as its connection did not respond within {}s",
+            self.key,
+            LOCK_HEARTBEAT_TIMEOUT.as_secs()
+          )
+        }
+      }
+    }
+  }
+
+  // Closing the connection ends the session, which releases the lock
+  // The session also ends when the connection cannot be closed properly, e.g. after it has been dropped, as it is discarded anyway
+  pub async fn release(self) {
+    if let Err(e) = self.connection.close().await {
+      debug!("released lock {} by discarding its connection. {}", self.key, e);
+    }
+  }
+}
@@ -0,0 +1,261 @@
+use crate::config;
+use chrono::{DateTime, Datelike, Days, NaiveDate, NaiveTime, TimeDelta, Utc, Weekday};
+use chrono_tz::Tz;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::str::FromStr;
+
+// How far back to look for the latest trading session, which covers weekends and long holidays
+const MAX_SESSION_LOOKBACK_DAYS: u64 = 14;
+
+fn default_settle_minutes() -> i64 {
+  15
+}
+
+#[derive(Debug, Deserialize)]
+struct MarketCalendarFileEntry {
+  timezone: String,
+  open: String,
+  close: String,
+  #[serde(default = "default_settle_minutes")]
+  settle_minutes: i64,
+  #[serde(default)]
+  holidays: Vec<String>,
+}
+
+// Trading hours of a market in its local timezone, which is open on weekdays except holidays
+#[derive(Debug, Clone)]
+pub struct MarketCalendar {
+  pub timezone: Tz,
+  pub open: NaiveTime,
+  pub close: NaiveTime,
+  // How long after close the closing prices take to be published, e.g. after the closing auction
+  pub settle_delay: TimeDelta,
+  pub holidays: HashSet<NaiveDate>,
+}
+
+impl MarketCalendar {
+  fn is_trading_day(&self, date: NaiveDate) -> bool {
+    !matches!(date.weekday(), Weekday::Sat | Weekday::Sun) && !self.holidays.contains(&date)
+  }
+
+  pub fn is_open(&self, now: DateTime<Utc>) -> bool {
+    let local_now = now.with_timezone(&self.timezone);
+    let time = local_now.time();
+    self.is_trading_day(local_now.date_naive()) && time >= self.open && time < self.close
+  }
+
+  // Holidays are listed per year, a calendar without any for the year is likely not extended yet
+  pub fn has_holidays_in_year(&self, year: i32) -> bool {
+    self.holidays.iter().any(|date| date.year() == year)
+  }
+
+  // Local date of the latest trading session which has already closed and whose closing prices have settled
+  pub fn get_latest_closed_session(&self, now: DateTime<Utc>) -> Option<NaiveDate> {
+    let local_now = now.with_timezone(&self.timezone);
+    let today = local_now.date_naive();
+    let settled_at = self.close + self.settle_delay;
+    (0..=MAX_SESSION_LOOKBACK_DAYS)
+      .filter_map(|days| today.checked_sub_days(Days::new(days)))
+      .filter(|date| *date < today || local_now.time() >= settled_at)
+      .find(|date| self.is_trading_day(*date))
+  }
+}
+
+// Load trading calendars of markets keyed by country code from the file in environment variable 'MARKET_CALENDAR_FILE'
+// Markets without a calendar are treated as always open
+pub fn load_market_calendars() -> Result<HashMap<String, MarketCalendar>, String> {
+  let path = config::load_market_calendar_file();
+  let content = fs::read_to_string(&path).map_err(|e| format!("failed to read market calendar file {path}. {}", e))?;
+  let entries = serde_json::from_str::<HashMap<String, MarketCalendarFileEntry>>(&content)
+    .map_err(|e| format!("failed to parse market calendar file {path}. {}", e))?;
+
+  let mut calendars = HashMap::new();
+  for (country_code, entry) in entries.into_iter() {
+    let timezone =
+      Tz::from_str(&entry.timezone).map_err(|e| format!("invalid timezone {} for market {country_code}. {}", entry.timezone, e))?;
+    let parse_time = |time: &str| {
+      NaiveTime::parse_from_str(time, "%H:%M").map_err(|e| format!("invalid trading hours {time} for market {country_code}. {}", e))
+    };
+    let (open, close) = (parse_time(&entry.open)?, parse_time(&entry.close)?);
+    if open >= close {
+      return Err(format!("market {country_code} closes at {close} before it opens at {open}"));
+    }
+    // Closing prices have to settle on the same local date, so that the session is still told apart from the next one
+    let settle_delay = TimeDelta::try_minutes(entry.settle_minutes)
+      .filter(|delay| *delay >= TimeDelta::zero() && close.overflowing_add_signed(*delay).1 == 0)
+      .ok_or_else(|| {
+        format!(
+          "invalid settle minutes {} for market {country_code}. expected closing prices to settle before midnight",
+          entry.settle_minutes
+        )
+      })?;
+    let holidays = entry
+      .holidays
+      .iter()
+      .map(|date| {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|e| format!("invalid holiday {date} for market {country_code}. {}", e))
+      })
+      .collect::<Result<HashSet<NaiveDate>, String>>()?;
+    calendars.insert(
+      country_code.to_uppercase(),
+      MarketCalendar {
+        timezone,
+        open,
+        close,
+        settle_delay,
+        holidays,
+      },
+    );
+  }
+
+  Ok(calendars)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // New York is 4 hours behind UTC in July and 5 hours in January
+  fn us_calendar() -> MarketCalendar {
+    MarketCalendar {
+      timezone: chrono_tz::America::New_York,
+      open: NaiveTime::from_hms_opt(9, 30, 0).unwrap(),
+      close: NaiveTime::from_hms_opt(16, 0, 0).unwrap(),
+      settle_delay: TimeDelta::zero(),
+      holidays: HashSet::from([date(2024, 7, 4), date(2024, 12, 25)]),
+    }
+  }
+
+  fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year, month, day).unwrap()
+  }
+
+  fn at(utc: &str) -> DateTime<Utc> {
+    utc.parse().unwrap()
+  }
+
+  #[test]
+  fn is_open_only_within_trading_hours() {
+    let calendar = us_calendar();
+    // Wednesday 3 Jul 2024
+    assert!(!calendar.is_open(at("2024-07-03T13:29:59Z")));
+    assert!(calendar.is_open(at("2024-07-03T13:30:00Z")));
+    assert!(calendar.is_open(at("2024-07-03T19:59:59Z")));
+    assert!(!calendar.is_open(at("2024-07-03T20:00:00Z")));
+    assert!(!calendar.is_open(at("2024-07-04T01:00:00Z")));
+  }
+
+  #[test]
+  fn is_open_follows_daylight_saving_time() {
+    let calendar = us_calendar();
+    // Tuesday 2 Jan 2024 in standard time
+    assert!(!calendar.is_open(at("2024-01-02T14:29:00Z")));
+    assert!(calendar.is_open(at("2024-01-02T14:30:00Z")));
+    assert!(calendar.is_open(at("2024-01-02T20:59:00Z")));
+    assert!(!calendar.is_open(at("2024-01-02T21:00:00Z")));
+  }
+
+  #[test]
+  fn is_closed_on_weekends_and_holidays() {
+    let calendar = us_calendar();
+    // Independence Day on Thursday, then Saturday and Sunday
+    assert!(!calendar.is_open(at("2024-07-04T15:00:00Z")));
+    assert!(calendar.is_open(at("2024-07-05T15:00:00Z")));
+    assert!(!calendar.is_open(at("2024-07-06T15:00:00Z")));
+    assert!(!calendar.is_open(at("2024-07-07T15:00:00Z")));
+    assert!(calendar.is_open(at("2024-07-08T15:00:00Z")));
+  }
+
+  #[test]
+  fn latest_closed_session_before_and_after_close() {
+    let calendar = us_calendar();
+    // Session of the day is not closed before open or while trading
+    assert_eq!(
+      calendar.get_latest_closed_session(at("2024-07-03T12:00:00Z")),
+      Some(date(2024, 7, 2))
+    );
+    assert_eq!(
+      calendar.get_latest_closed_session(at("2024-07-03T19:59:59Z")),
+      Some(date(2024, 7, 2))
+    );
+    // First tick after close
+    assert_eq!(
+      calendar.get_latest_closed_session(at("2024-07-03T20:00:00Z")),
+      Some(date(2024, 7, 3))
+    );
+    // Still the same local date in New York after midnight in UTC
+    assert_eq!(
+      calendar.get_latest_closed_session(at("2024-07-04T02:00:00Z")),
+      Some(date(2024, 7, 3))
+    );
+  }
+
+  #[test]
+  fn latest_closed_session_waits_for_closing_prices_to_settle() {
+    let calendar = MarketCalendar {
+      settle_delay: TimeDelta::try_minutes(15).unwrap(),
+      ..us_calendar()
+    };
+    // Market is closed while the closing prices settle, but the session of the day is not counted as closed yet
+    assert!(!calendar.is_open(at("2024-07-03T20:00:00Z")));
+    assert_eq!(
+      calendar.get_latest_closed_session(at("2024-07-03T20:00:00Z")),
+      Some(date(2024, 7, 2))
+    );
+    assert_eq!(
+      calendar.get_latest_closed_session(at("2024-07-03T20:14:59Z")),
+      Some(date(2024, 7, 2))
+    );
+    assert_eq!(
+      calendar.get_latest_closed_session(at("2024-07-03T20:15:00Z")),
+      Some(date(2024, 7, 3))
+    );
+  }
+
+  #[test]
+  fn latest_closed_session_skips_weekends_and_holidays() {
+    let calendar = us_calendar();
+    assert_eq!(
+      calendar.get_latest_closed_session(at("2024-07-04T21:00:00Z")),
+      Some(date(2024, 7, 3))
+    );
+    assert_eq!(
+      calendar.get_latest_closed_session(at("2024-07-05T15:00:00Z")),
+      Some(date(2024, 7, 3))
+    );
+    assert_eq!(
+      calendar.get_latest_closed_session(at("2024-07-06T15:00:00Z")),
+      Some(date(2024, 7, 5))
+    );
+    // Monday before open, which is still Sunday in New York
+    assert_eq!(
+      calendar.get_latest_closed_session(at("2024-07-08T02:00:00Z")),
+      Some(date(2024, 7, 5))
+    );
+    assert_eq!(
+      calendar.get_latest_closed_session(at("2024-07-08T13:00:00Z")),
+      Some(date(2024, 7, 5))
+    );
+    // Christmas on Wednesday
+    assert_eq!(
+      calendar.get_latest_closed_session(at("2024-12-26T15:00:00Z")),
+      Some(date(2024, 12, 24))
+    );
+  }
+
+  #[test]
+  fn latest_closed_session_is_none_without_trading_day_in_lookback() {
+    let mut calendar = us_calendar();
+    calendar.holidays.extend((1..=20).map(|day| date(2024, 7, day)));
+    assert_eq!(calendar.get_latest_closed_session(at("2024-07-20T21:00:00Z")), None);
+  }
+
+  #[test]
+  fn has_holidays_only_in_listed_years() {
+    let calendar = us_calendar();
+    assert!(calendar.has_holidays_in_year(2024));
+    assert!(!calendar.has_holidays_in_year(2025));
+  }
+}
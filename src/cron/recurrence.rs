@@ -0,0 +1,538 @@
+use std::fmt;
+use time::{util::days_in_year_month, Date, Duration, Month, OffsetDateTime};
+
+const SECONDS_PER_DAY: i64 = 86400;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecurrenceUnit {
+  Daily,
+  Weekly,
+  Monthly,
+  Quarterly,
+  Yearly,
+}
+
+// Rule of a series of occurrences repeating every interval of the unit from an anchor occurrence
+// Occurrences of monthly, quarterly and yearly rules keep the day of month of the anchor,
+// clamped to the end of shorter months, e.g. 31 Jan, 29 Feb, 31 Mar in a leap year
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Recurrence {
+  pub unit: RecurrenceUnit,
+  pub interval: u32,
+}
+
+impl fmt::Display for Recurrence {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let unit = match self.unit {
+      RecurrenceUnit::Daily => "day",
+      RecurrenceUnit::Weekly => "week",
+      RecurrenceUnit::Monthly => "month",
+      RecurrenceUnit::Quarterly => "quarter",
+      RecurrenceUnit::Yearly => "year",
+    };
+    match self.interval {
+      1 => write!(f, "every {unit}"),
+      interval => write!(f, "every {interval} {unit}s"),
+    }
+  }
+}
+
+impl Recurrence {
+  // Interpret frequency of future payment, which is stored in seconds with 30 days for a month and 365 days for a year
+  // 29 and 31 days are treated as a month as well, the same as before there was a recurrence engine
+  pub fn from_frequency(frequency: i64) -> Result<Self, String> {
+    if frequency <= 0 || frequency % SECONDS_PER_DAY != 0 {
+      return Err(format!(
+        "invalid frequency {frequency}. expected a positive number of whole days in seconds"
+      ));
+    }
+    let days = frequency / SECONDS_PER_DAY;
+    let (unit, interval) = match days {
+      days if days % 365 == 0 => (RecurrenceUnit::Yearly, days / 365),
+      366 => (RecurrenceUnit::Yearly, 1),
+      days if days % 90 == 0 => (RecurrenceUnit::Quarterly, days / 90),
+      days if days % 30 == 0 => (RecurrenceUnit::Monthly, days / 30),
+      29 | 31 => (RecurrenceUnit::Monthly, 1),
+      days if days % 7 == 0 => (RecurrenceUnit::Weekly, days / 7),
+      days => (RecurrenceUnit::Daily, days),
+    };
+    let interval = u32::try_from(interval).map_err(|_| format!("invalid frequency {frequency}. it is too long"))?;
+
+    Ok(Recurrence { unit, interval })
+  }
+
+  // Occurrence n intervals after the anchor, which is the anchor itself when n is 0
+  // Always counted from the anchor so that clamping to the end of a short month does not shift the following occurrences
+  pub fn nth_occurrence(&self, anchor: OffsetDateTime, n: u32) -> Option<OffsetDateTime> {
+    let steps = i64::from(self.interval).checked_mul(i64::from(n))?;
+    let date = match self.unit {
+      RecurrenceUnit::Daily => anchor.date().checked_add(Duration::days(steps)),
+      RecurrenceUnit::Weekly => anchor.date().checked_add(Duration::weeks(steps)),
+      RecurrenceUnit::Monthly => add_months(anchor.date(), steps),
+      RecurrenceUnit::Quarterly => add_months(anchor.date(), steps.checked_mul(3)?),
+      RecurrenceUnit::Yearly => add_months(anchor.date(), steps.checked_mul(12)?),
+    }?;
+
+    Some(anchor.replace_date(date))
+  }
+
+  // First occurrence of the series strictly after the given time, which is the anchor itself when it is later
+  pub fn next_occurrence(&self, anchor: OffsetDateTime, after: OffsetDateTime) -> Option<OffsetDateTime> {
+    // Start from an estimated number of intervals that is never beyond the answer, then walk forward
+    let mut n = u32::try_from(self.estimate_elapsed_intervals(anchor, after).saturating_sub(1).max(0)).ok()?;
+    loop {
+      let occurrence = self.nth_occurrence(anchor, n)?;
+      if occurrence > after {
+        return Some(occurrence);
+      }
+      n = n.checked_add(1)?;
+    }
+  }
+
+  // Whether the time is one of the occurrences of the series
+  pub fn is_occurrence(&self, anchor: OffsetDateTime, time: OffsetDateTime) -> bool {
+    time >= anchor && self.next_occurrence(anchor, time - Duration::nanoseconds(1)) == Some(time)
+  }
+
+  fn estimate_elapsed_intervals(&self, anchor: OffsetDateTime, after: OffsetDateTime) -> i64 {
+    let interval = i64::from(self.interval);
+    let elapsed_months =
+      (i64::from(after.year()) * 12 + i64::from(after.month() as u8)) - (i64::from(anchor.year()) * 12 + i64::from(anchor.month() as u8));
+    match self.unit {
+      RecurrenceUnit::Daily => (after - anchor).whole_days() / interval,
+      RecurrenceUnit::Weekly => (after - anchor).whole_weeks() / interval,
+      RecurrenceUnit::Monthly => elapsed_months / interval,
+      RecurrenceUnit::Quarterly => elapsed_months / (3 * interval),
+      RecurrenceUnit::Yearly => elapsed_months / (12 * interval),
+    }
+  }
+}
+
+// Add months to date, clamping the day to the end of the resulting month
+fn add_months(date: Date, months: i64) -> Option<Date> {
+  let month_index = i64::from(date.year()).checked_mul(12)? + i64::from(date.month() as u8 - 1) + months;
+  let year = i32::try_from(month_index.div_euclid(12)).ok()?;
+  let month = Month::try_from(u8::try_from(month_index.rem_euclid(12) + 1).ok()?).ok()?;
+  let day = date.day().min(days_in_year_month(year, month));
+  Date::from_calendar_date(year, month, day).ok()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use time::{Time, UtcOffset};
+
+  fn date(year: i32, month: u8, day: u8) -> Date {
+    Date::from_calendar_date(year, Month::try_from(month).unwrap(), day).unwrap()
+  }
+
+  fn at(year: i32, month: u8, day: u8) -> OffsetDateTime {
+    date(year, month, day).with_time(Time::from_hms(9, 30, 0).unwrap()).assume_utc()
+  }
+
+  fn rule(unit: RecurrenceUnit, interval: u32) -> Recurrence {
+    Recurrence { unit, interval }
+  }
+
+  fn occurrences(recurrence: Recurrence, anchor: OffsetDateTime, count: u32) -> Vec<Date> {
+    (0..count).map(|n| recurrence.nth_occurrence(anchor, n).unwrap().date()).collect()
+  }
+
+  fn all_rules() -> Vec<Recurrence> {
+    let mut rules = vec![];
+    for unit in [
+      RecurrenceUnit::Daily,
+      RecurrenceUnit::Weekly,
+      RecurrenceUnit::Monthly,
+      RecurrenceUnit::Quarterly,
+      RecurrenceUnit::Yearly,
+    ] {
+      for interval in [1, 2, 3, 5] {
+        rules.push(rule(unit, interval));
+      }
+    }
+    rules
+  }
+
+  fn all_dates(from: Date, to: Date) -> Vec<Date> {
+    let mut dates = vec![];
+    let mut date = from;
+    while date <= to {
+      dates.push(date);
+      date = date.next_day().unwrap();
+    }
+    dates
+  }
+
+  #[test]
+  fn from_frequency_maps_days_to_units() {
+    let cases = [
+      (1, RecurrenceUnit::Daily, 1),
+      (3, RecurrenceUnit::Daily, 3),
+      (10, RecurrenceUnit::Daily, 10),
+      (7, RecurrenceUnit::Weekly, 1),
+      (14, RecurrenceUnit::Weekly, 2),
+      (28, RecurrenceUnit::Weekly, 4),
+      (29, RecurrenceUnit::Monthly, 1),
+      (30, RecurrenceUnit::Monthly, 1),
+      (31, RecurrenceUnit::Monthly, 1),
+      (60, RecurrenceUnit::Monthly, 2),
+      (150, RecurrenceUnit::Monthly, 5),
+      (90, RecurrenceUnit::Quarterly, 1),
+      (180, RecurrenceUnit::Quarterly, 2),
+      (365, RecurrenceUnit::Yearly, 1),
+      (366, RecurrenceUnit::Yearly, 1),
+      (730, RecurrenceUnit::Yearly, 2),
+    ];
+    for (days, unit, interval) in cases {
+      assert_eq!(
+        Recurrence::from_frequency(days * SECONDS_PER_DAY),
+        Ok(rule(unit, interval)),
+        "frequency of {days} days"
+      );
+    }
+  }
+
+  #[test]
+  fn from_frequency_rejects_invalid_frequency() {
+    for frequency in [0, -SECONDS_PER_DAY, 3600, SECONDS_PER_DAY + 1, SECONDS_PER_DAY * 30 - 1] {
+      assert!(Recurrence::from_frequency(frequency).is_err(), "frequency {frequency}");
+    }
+  }
+
+  #[test]
+  fn nth_occurrence_of_zero_is_anchor() {
+    let anchor = at(2024, 1, 31);
+    for recurrence in all_rules() {
+      assert_eq!(recurrence.nth_occurrence(anchor, 0), Some(anchor), "{recurrence}");
+    }
+  }
+
+  #[test]
+  fn monthly_clamps_31st_to_end_of_every_month_in_leap_year() {
+    let expected = [
+      date(2024, 1, 31),
+      date(2024, 2, 29),
+      date(2024, 3, 31),
+      date(2024, 4, 30),
+      date(2024, 5, 31),
+      date(2024, 6, 30),
+      date(2024, 7, 31),
+      date(2024, 8, 31),
+      date(2024, 9, 30),
+      date(2024, 10, 31),
+      date(2024, 11, 30),
+      date(2024, 12, 31),
+      date(2025, 1, 31),
+    ];
+    assert_eq!(occurrences(rule(RecurrenceUnit::Monthly, 1), at(2024, 1, 31), 13), expected);
+  }
+
+  #[test]
+  fn monthly_clamps_31st_to_end_of_every_month_in_common_year() {
+    let expected = [
+      date(2023, 1, 31),
+      date(2023, 2, 28),
+      date(2023, 3, 31),
+      date(2023, 4, 30),
+      date(2023, 5, 31),
+      date(2023, 6, 30),
+      date(2023, 7, 31),
+      date(2023, 8, 31),
+      date(2023, 9, 30),
+      date(2023, 10, 31),
+      date(2023, 11, 30),
+      date(2023, 12, 31),
+      date(2024, 1, 31),
+    ];
+    assert_eq!(occurrences(rule(RecurrenceUnit::Monthly, 1), at(2023, 1, 31), 13), expected);
+  }
+
+  #[test]
+  fn monthly_does_not_drift_after_clamping() {
+    let recurrence = rule(RecurrenceUnit::Monthly, 1);
+    assert_eq!(
+      occurrences(recurrence, at(2024, 1, 30), 4),
+      [date(2024, 1, 30), date(2024, 2, 29), date(2024, 3, 30), date(2024, 4, 30)]
+    );
+    assert_eq!(
+      occurrences(recurrence, at(2023, 1, 29), 4),
+      [date(2023, 1, 29), date(2023, 2, 28), date(2023, 3, 29), date(2023, 4, 29)]
+    );
+    assert_eq!(
+      occurrences(recurrence, at(2024, 3, 31), 3),
+      [date(2024, 3, 31), date(2024, 4, 30), date(2024, 5, 31)]
+    );
+  }
+
+  #[test]
+  fn monthly_keeps_days_existing_in_every_month() {
+    for day in 1..=28 {
+      let anchor = at(2023, 1, day);
+      for (n, date) in occurrences(rule(RecurrenceUnit::Monthly, 1), anchor, 36).into_iter().enumerate() {
+        assert_eq!(date.day(), day, "occurrence {n} of day {day}");
+      }
+    }
+  }
+
+  #[test]
+  fn monthly_advances_year_past_december() {
+    let recurrence = rule(RecurrenceUnit::Monthly, 1);
+    assert_eq!(
+      occurrences(recurrence, at(2023, 11, 15), 4),
+      [date(2023, 11, 15), date(2023, 12, 15), date(2024, 1, 15), date(2024, 2, 15)]
+    );
+    assert_eq!(recurrence.nth_occurrence(at(2023, 12, 31), 1), Some(at(2024, 1, 31)));
+    assert_eq!(recurrence.nth_occurrence(at(2023, 1, 31), 25), Some(at(2025, 2, 28)));
+  }
+
+  #[test]
+  fn every_n_months_across_years() {
+    assert_eq!(
+      occurrences(rule(RecurrenceUnit::Monthly, 2), at(2023, 12, 31), 4),
+      [date(2023, 12, 31), date(2024, 2, 29), date(2024, 4, 30), date(2024, 6, 30)]
+    );
+    assert_eq!(
+      occurrences(rule(RecurrenceUnit::Monthly, 5), at(2023, 10, 31), 4),
+      [date(2023, 10, 31), date(2024, 3, 31), date(2024, 8, 31), date(2025, 1, 31)]
+    );
+    assert_eq!(
+      occurrences(rule(RecurrenceUnit::Monthly, 13), at(2023, 1, 29), 3),
+      [date(2023, 1, 29), date(2024, 2, 29), date(2025, 3, 29)]
+    );
+  }
+
+  #[test]
+  fn quarterly_clamps_and_advances_year() {
+    assert_eq!(
+      occurrences(rule(RecurrenceUnit::Quarterly, 1), at(2023, 11, 30), 5),
+      [
+        date(2023, 11, 30),
+        date(2024, 2, 29),
+        date(2024, 5, 30),
+        date(2024, 8, 30),
+        date(2024, 11, 30)
+      ]
+    );
+    assert_eq!(
+      occurrences(rule(RecurrenceUnit::Quarterly, 1), at(2024, 11, 30), 2),
+      [date(2024, 11, 30), date(2025, 2, 28)]
+    );
+    assert_eq!(
+      occurrences(rule(RecurrenceUnit::Quarterly, 1), at(2023, 12, 31), 5),
+      [
+        date(2023, 12, 31),
+        date(2024, 3, 31),
+        date(2024, 6, 30),
+        date(2024, 9, 30),
+        date(2024, 12, 31)
+      ]
+    );
+    assert_eq!(
+      occurrences(rule(RecurrenceUnit::Quarterly, 2), at(2023, 8, 31), 3),
+      [date(2023, 8, 31), date(2024, 2, 29), date(2024, 8, 31)]
+    );
+  }
+
+  #[test]
+  fn yearly_clamps_leap_day_to_end_of_february() {
+    assert_eq!(
+      occurrences(rule(RecurrenceUnit::Yearly, 1), at(2024, 2, 29), 5),
+      [
+        date(2024, 2, 29),
+        date(2025, 2, 28),
+        date(2026, 2, 28),
+        date(2027, 2, 28),
+        date(2028, 2, 29)
+      ]
+    );
+    assert_eq!(
+      occurrences(rule(RecurrenceUnit::Yearly, 4), at(2024, 2, 29), 3),
+      [date(2024, 2, 29), date(2028, 2, 29), date(2032, 2, 29)]
+    );
+    assert_eq!(
+      occurrences(rule(RecurrenceUnit::Yearly, 2), at(2024, 2, 29), 3),
+      [date(2024, 2, 29), date(2026, 2, 28), date(2028, 2, 29)]
+    );
+  }
+
+  #[test]
+  fn yearly_handles_century_leap_years() {
+    // 2100 is not a leap year while 2000 is
+    assert_eq!(
+      occurrences(rule(RecurrenceUnit::Yearly, 4), at(2096, 2, 29), 3),
+      [date(2096, 2, 29), date(2100, 2, 28), date(2104, 2, 29)]
+    );
+    assert_eq!(
+      occurrences(rule(RecurrenceUnit::Yearly, 4), at(1996, 2, 29), 3),
+      [date(1996, 2, 29), date(2000, 2, 29), date(2004, 2, 29)]
+    );
+  }
+
+  #[test]
+  fn yearly_keeps_other_days() {
+    assert_eq!(
+      occurrences(rule(RecurrenceUnit::Yearly, 1), at(2023, 12, 31), 3),
+      [date(2023, 12, 31), date(2024, 12, 31), date(2025, 12, 31)]
+    );
+    assert_eq!(
+      occurrences(rule(RecurrenceUnit::Yearly, 1), at(2023, 3, 1), 3),
+      [date(2023, 3, 1), date(2024, 3, 1), date(2025, 3, 1)]
+    );
+  }
+
+  #[test]
+  fn daily_crosses_leap_day_and_year_end() {
+    let recurrence = rule(RecurrenceUnit::Daily, 1);
+    assert_eq!(
+      occurrences(recurrence, at(2024, 2, 28), 3),
+      [date(2024, 2, 28), date(2024, 2, 29), date(2024, 3, 1)]
+    );
+    assert_eq!(occurrences(recurrence, at(2023, 2, 28), 2), [date(2023, 2, 28), date(2023, 3, 1)]);
+    assert_eq!(occurrences(recurrence, at(2023, 12, 31), 2), [date(2023, 12, 31), date(2024, 1, 1)]);
+    assert_eq!(
+      rule(RecurrenceUnit::Daily, 3).nth_occurrence(at(2024, 2, 27), 1),
+      Some(at(2024, 3, 1))
+    );
+    assert_eq!(recurrence.nth_occurrence(at(2024, 1, 1), 366), Some(at(2025, 1, 1)));
+    assert_eq!(recurrence.nth_occurrence(at(2023, 1, 1), 365), Some(at(2024, 1, 1)));
+  }
+
+  #[test]
+  fn weekly_crosses_leap_day_and_year_end() {
+    assert_eq!(
+      occurrences(rule(RecurrenceUnit::Weekly, 1), at(2023, 12, 28), 3),
+      [date(2023, 12, 28), date(2024, 1, 4), date(2024, 1, 11)]
+    );
+    assert_eq!(
+      occurrences(rule(RecurrenceUnit::Weekly, 1), at(2024, 2, 22), 3),
+      [date(2024, 2, 22), date(2024, 2, 29), date(2024, 3, 7)]
+    );
+    assert_eq!(
+      occurrences(rule(RecurrenceUnit::Weekly, 2), at(2024, 12, 25), 3),
+      [date(2024, 12, 25), date(2025, 1, 8), date(2025, 1, 22)]
+    );
+  }
+
+  #[test]
+  fn occurrences_keep_time_of_day_and_offset() {
+    let anchor = date(2024, 1, 31)
+      .with_time(Time::from_hms(23, 45, 10).unwrap())
+      .assume_offset(UtcOffset::from_hms(8, 0, 0).unwrap());
+    for recurrence in all_rules() {
+      let occurrence = recurrence.nth_occurrence(anchor, 7).unwrap();
+      assert_eq!(occurrence.time(), anchor.time(), "{recurrence}");
+      assert_eq!(occurrence.offset(), anchor.offset(), "{recurrence}");
+    }
+  }
+
+  #[test]
+  fn occurrences_are_strictly_increasing_for_every_anchor() {
+    for anchor_date in all_dates(date(2023, 1, 1), date(2025, 12, 31)) {
+      let anchor = anchor_date.midnight().assume_utc();
+      for recurrence in all_rules() {
+        let mut previous = anchor;
+        for n in 1..=30 {
+          let occurrence = recurrence.nth_occurrence(anchor, n).unwrap();
+          assert!(occurrence > previous, "{recurrence} from {anchor_date} at occurrence {n}");
+          previous = occurrence;
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn month_based_occurrences_keep_anchor_day_unless_month_is_shorter() {
+    for anchor_date in all_dates(date(2023, 1, 1), date(2025, 12, 31)) {
+      let anchor = anchor_date.midnight().assume_utc();
+      for recurrence in all_rules()
+        .into_iter()
+        .filter(|r| matches!(r.unit, RecurrenceUnit::Monthly | RecurrenceUnit::Quarterly | RecurrenceUnit::Yearly))
+      {
+        for n in 1..=30 {
+          let occurrence = recurrence.nth_occurrence(anchor, n).unwrap().date();
+          let days_in_month = days_in_year_month(occurrence.year(), occurrence.month());
+          assert_eq!(
+            occurrence.day(),
+            anchor_date.day().min(days_in_month),
+            "{recurrence} from {anchor_date} at occurrence {n}"
+          );
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn next_occurrence_matches_walking_through_occurrences() {
+    let afters = [
+      at(2022, 6, 1),
+      at(2024, 2, 28),
+      at(2024, 2, 29),
+      at(2024, 3, 1),
+      at(2024, 12, 31),
+      at(2025, 1, 1),
+      at(2026, 7, 15),
+    ];
+    for anchor_date in all_dates(date(2023, 1, 1), date(2025, 12, 31)) {
+      let anchor = anchor_date.with_time(Time::from_hms(9, 30, 0).unwrap()).assume_utc();
+      for recurrence in all_rules() {
+        for after in afters {
+          let expected = (0..)
+            .map(|n| recurrence.nth_occurrence(anchor, n).unwrap())
+            .find(|occurrence| *occurrence > after);
+          assert_eq!(
+            recurrence.next_occurrence(anchor, after),
+            expected,
+            "{recurrence} from {anchor_date} after {after}"
+          );
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn next_occurrence_is_strictly_after() {
+    let recurrence = rule(RecurrenceUnit::Monthly, 1);
+    let anchor = at(2024, 1, 31);
+    assert_eq!(recurrence.next_occurrence(anchor, anchor - Duration::seconds(1)), Some(anchor));
+    assert_eq!(recurrence.next_occurrence(anchor, anchor), Some(at(2024, 2, 29)));
+    assert_eq!(recurrence.next_occurrence(anchor, at(2024, 2, 29)), Some(at(2024, 3, 31)));
+    assert_eq!(
+      recurrence.next_occurrence(anchor, at(2024, 2, 29) - Duration::seconds(1)),
+      Some(at(2024, 2, 29))
+    );
+  }
+
+  #[test]
+  fn next_occurrence_far_after_anchor() {
+    let recurrence = rule(RecurrenceUnit::Monthly, 1);
+    assert_eq!(recurrence.next_occurrence(at(2000, 1, 31), at(2024, 2, 15)), Some(at(2024, 2, 29)));
+    assert_eq!(
+      rule(RecurrenceUnit::Daily, 1).next_occurrence(at(2000, 1, 1), at(2024, 2, 15)),
+      Some(at(2024, 2, 16))
+    );
+    assert_eq!(
+      rule(RecurrenceUnit::Yearly, 1).next_occurrence(at(2000, 2, 29), at(2101, 1, 1)),
+      Some(at(2101, 2, 28))
+    );
+  }
+
+  #[test]
+  fn is_occurrence_only_for_dates_in_series() {
+    let recurrence = rule(RecurrenceUnit::Monthly, 1);
+    let anchor = at(2024, 1, 31);
+    assert!(recurrence.is_occurrence(anchor, anchor));
+    assert!(recurrence.is_occurrence(anchor, at(2024, 2, 29)));
+    assert!(recurrence.is_occurrence(anchor, at(2024, 3, 31)));
+    assert!(!recurrence.is_occurrence(anchor, at(2024, 3, 29)));
+    assert!(!recurrence.is_occurrence(anchor, at(2023, 12, 31)));
+    assert!(!recurrence.is_occurrence(anchor, at(2024, 2, 29) + Duration::hours(1)));
+  }
+
+  #[test]
+  fn display_describes_rule() {
+    assert_eq!(rule(RecurrenceUnit::Monthly, 1).to_string(), "every month");
+    assert_eq!(rule(RecurrenceUnit::Weekly, 2).to_string(), "every 2 weeks");
+    assert_eq!(rule(RecurrenceUnit::Yearly, 3).to_string(), "every 3 years");
+  }
+}
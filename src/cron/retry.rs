@@ -0,0 +1,138 @@
+use rand::Rng;
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+// Errors returned by cronjob tasks are treated as permanent unless they are marked as retryable
+#[derive(Debug)]
+pub enum JobError {
+  // Transient failure, e.g. network error, which may succeed if the task runs again
+  Retryable(String),
+  // Failure that will happen again no matter how many times the task runs, e.g. missing config
+  Permanent(String),
+  // Not a failure, the task found nothing to do at this moment, e.g. market is closed, which is not kept in job run history
+  Skipped(String),
+}
+
+impl fmt::Display for JobError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      JobError::Retryable(e) => write!(f, "{e}"),
+      JobError::Permanent(e) => write!(f, "{e}"),
+      JobError::Skipped(reason) => write!(f, "{reason}"),
+    }
+  }
+}
+
+impl Error for JobError {}
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+  // Including the first attempt, so 1 means never retry
+  pub max_attempts: u32,
+  pub base_delay: Duration,
+  pub max_delay: Duration,
+  // Ratio between 0 and 1 of the backoff delay to be randomly taken away so that retries do not happen in lockstep
+  pub jitter: f64,
+}
+
+impl RetryPolicy {
+  // Exponential backoff delay before the next attempt after given attempt failed, capped at max delay
+  pub fn get_backoff_delay(&self, attempt: u32) -> Duration {
+    let exponential_delay = self.base_delay.saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)));
+    let delay = exponential_delay.min(self.max_delay);
+    let jitter = rand::thread_rng().gen_range(0.0..=self.jitter);
+    delay.mul_f64(1.0 - jitter)
+  }
+
+  // Whether the task should run again after given attempt failed with the error
+  pub fn should_retry(&self, e: &(dyn Error + 'static), attempt: u32) -> bool {
+    is_retryable(e) && attempt < self.max_attempts
+  }
+}
+
+pub fn is_retryable(e: &(dyn Error + 'static)) -> bool {
+  matches!(e.downcast_ref::<JobError>(), Some(JobError::Retryable(_)))
+}
+
+pub fn is_skipped(e: &(dyn Error + 'static)) -> bool {
+  matches!(e.downcast_ref::<JobError>(), Some(JobError::Skipped(_)))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn policy(jitter: f64) -> RetryPolicy {
+    RetryPolicy {
+      jitter,
+      max_attempts: 3,
+      base_delay: Duration::from_secs(5),
+      max_delay: Duration::from_secs(60),
+    }
+  }
+
+  #[test]
+  fn backoff_delay_doubles_until_max_delay() {
+    let policy = policy(0.0);
+    let delays = (1..=6).map(|attempt| policy.get_backoff_delay(attempt)).collect::<Vec<Duration>>();
+
+    assert_eq!(delays, [5, 10, 20, 40, 60, 60].map(Duration::from_secs));
+    assert!(delays.windows(2).all(|w| w[0] <= w[1]));
+  }
+
+  #[test]
+  fn backoff_delay_is_capped_for_many_attempts() {
+    let policy = policy(0.0);
+
+    assert_eq!(policy.get_backoff_delay(100), Duration::from_secs(60));
+    assert_eq!(policy.get_backoff_delay(u32::MAX), Duration::from_secs(60));
+  }
+
+  #[test]
+  fn backoff_delay_stays_within_jitter_range() {
+    let jittered_policy = policy(0.5);
+    for attempt in 1..=6 {
+      let undelayed = policy(0.0).get_backoff_delay(attempt);
+      for _ in 0..100 {
+        let delay = jittered_policy.get_backoff_delay(attempt);
+        assert!(delay <= undelayed, "attempt {attempt} waits {delay:?} longer than {undelayed:?}");
+        assert!(
+          delay >= undelayed.mul_f64(0.5),
+          "attempt {attempt} waits {delay:?} shorter than half of {undelayed:?}"
+        );
+      }
+    }
+  }
+
+  #[test]
+  fn only_retryable_errors_are_retryable() {
+    let io_error = std::io::Error::other("connection reset");
+
+    assert!(is_retryable(&JobError::Retryable("timed out".to_string())));
+    assert!(!is_retryable(&JobError::Permanent("missing config".to_string())));
+    assert!(!is_retryable(&JobError::Skipped("market is closed".to_string())));
+    // Errors not classified by the task are permanent
+    assert!(!is_retryable(&io_error));
+    assert!(is_skipped(&JobError::Skipped("market is closed".to_string())));
+    assert!(!is_skipped(&JobError::Permanent("missing config".to_string())));
+  }
+
+  #[test]
+  fn retries_retryable_errors_until_max_attempts() {
+    let policy = policy(0.5);
+    let e = JobError::Retryable("timed out".to_string());
+
+    assert!(policy.should_retry(&e, 1));
+    assert!(policy.should_retry(&e, 2));
+    assert!(!policy.should_retry(&e, 3));
+  }
+
+  #[test]
+  fn never_retries_permanent_errors() {
+    let policy = policy(0.5);
+
+    assert!(!policy.should_retry(&JobError::Permanent("missing config".to_string()), 1));
+    assert!(!policy.should_retry(&JobError::Skipped("market is closed".to_string()), 1));
+  }
+}
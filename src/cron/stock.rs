@@ -1,45 +1,209 @@
-use crate::external::db::client;
+use super::market_calendar::MarketCalendar;
+use super::retry::JobError;
+use super::summary::RunSummary;
+use super::JobContext;
 use crate::external::db::query::country::get_country_by_code;
+use crate::external::db::query::currency::get_all_currencies;
+use crate::external::db::query::market_session::{get_last_closed_session, update_last_closed_session, UpdateLastClosedSessionParams};
 use crate::external::db::query::stock::{get_all_stocks_by_country_id, update_stock_current_price, UpdateStockCurrentPriceParams};
+use crate::external::db::query::stock_price_mismatch::{
+  delete_stock_price_mismatch, flag_stock_price_mismatch, FlagStockPriceMismatchParams,
+};
+use crate::external::yahoo_finance::YahooFinanceClient;
+use chrono::{Datelike, NaiveDate, Utc};
+use dotenvy::var;
+use rust_decimal::prelude::FromPrimitive;
+use rust_decimal::Decimal;
+use std::collections::HashMap;
 use std::error::Error;
+use std::sync::Arc;
+use time::{Date, Month};
+use tokio::task::JoinSet;
 use tracing::debug;
-use yahoo_finance_api::YahooConnector;
+use uuid::Uuid;
 
-#[tracing::instrument]
-pub async fn update_latest_us_stock_prices() -> Result<(), Box<dyn Error>> {
-  update_latest_stock_prices("US").await
+// Suffix appended to ticker by yahoo finance to tell which exchange the stock is listed on
+// https://help.yahoo.com/kb/SLN2310.html
+static YAHOO_TICKER_SUFFIXES: [(&str, &str); 22] = [
+  ("US", ""),
+  ("UK", ".L"),
+  ("GB", ".L"),
+  ("HK", ".HK"),
+  ("JP", ".T"),
+  ("DE", ".DE"),
+  ("FR", ".PA"),
+  ("NL", ".AS"),
+  ("ES", ".MC"),
+  ("IT", ".MI"),
+  ("CH", ".SW"),
+  ("SE", ".ST"),
+  ("CA", ".TO"),
+  ("AU", ".AX"),
+  ("NZ", ".NZ"),
+  ("SG", ".SI"),
+  ("IN", ".NS"),
+  ("KR", ".KS"),
+  ("TW", ".TW"),
+  ("BR", ".SA"),
+  ("MX", ".MX"),
+  ("ZA", ".JO"),
+];
+
+// Currencies quoted in sub-unit by yahoo finance with their main unit currency and the number of sub-units in one main unit
+static SUB_UNIT_CURRENCIES: [(&str, &str, i64); 4] = [("GBp", "GBP", 100), ("GBX", "GBP", 100), ("ZAc", "ZAR", 100), ("ILA", "ILS", 100)];
+
+// Resolve yahoo ticker suffix of country, which can be overridden by environment variable 'STOCK_TICKER_SUFFIX_<COUNTRY_CODE>'
+pub(super) fn get_yahoo_ticker_suffix(country_code: &str) -> Option<String> {
+  var(format!("STOCK_TICKER_SUFFIX_{}", country_code.to_uppercase()))
+    .ok()
+    .or_else(|| {
+      YAHOO_TICKER_SUFFIXES
+        .iter()
+        .find(|(code, _)| code.eq_ignore_ascii_case(country_code))
+        .map(|(_, suffix)| suffix.to_string())
+    })
+}
+
+// Tickers might have been stored with the suffix already
+pub(super) fn to_yahoo_ticker(ticker: &str, ticker_suffix: &str) -> String {
+  match ticker.ends_with(ticker_suffix) {
+    true => ticker.to_string(),
+    false => format!("{ticker}{ticker_suffix}"),
+  }
 }
 
-#[tracing::instrument]
-pub async fn update_latest_uk_stock_prices() -> Result<(), Box<dyn Error>> {
-  update_latest_stock_prices("UK").await
+// Main unit currency of a currency quoted in sub-unit, e.g. GBP for GBp, with the number of sub-units in one main unit
+pub(super) fn get_main_unit_currency(quote_currency: &str) -> Option<(&'static str, i64)> {
+  SUB_UNIT_CURRENCIES
+    .iter()
+    .find(|(sub_unit, _, _)| *sub_unit == quote_currency)
+    .map(|(_, currency, divisor)| (*currency, *divisor))
 }
 
-#[tracing::instrument]
-pub async fn update_latest_stock_prices(country_code: &str) -> Result<(), Box<dyn Error>> {
-  // Setup postgresql database connection
-  let pg_client = client::init_pg().await?;
+fn to_date(date: NaiveDate) -> Date {
+  Date::from_calendar_date(date.year(), Month::try_from(date.month() as u8).unwrap(), date.day() as u8).unwrap()
+}
+
+#[tracing::instrument(skip(context))]
+pub async fn update_latest_stock_prices(
+  context: JobContext,
+  country_code: &str,
+  market_calendar: Option<Arc<MarketCalendar>>,
+) -> Result<RunSummary, Box<dyn Error>> {
+  let pg_client = context.pg_client;
+
+  // While the market is closed, only update once after the closing prices of each session have settled
+  let mut closed_session = None;
+  if let Some(market_calendar) = market_calendar {
+    let now = Utc::now();
+    if !market_calendar.is_open(now) {
+      let latest_closed_session = market_calendar.get_latest_closed_session(now).map(to_date);
+      let last_closed_session = get_last_closed_session(&pg_client, country_code)
+        .await
+        .map_err(JobError::Retryable)?;
+      match latest_closed_session {
+        Some(session) if last_closed_session < Some(session) => closed_session = Some(session),
+        _ => return Err(JobError::Skipped(format!("market {country_code} is closed")).into()),
+      }
+      debug!(
+        "market {country_code} is closed. going to update stock prices after session {:?} closed",
+        closed_session
+      );
+    }
+  }
 
-  // Setup yahoo finance api client
-  let yahoo_finance_api_client = YahooConnector::new();
+  let ticker_suffix = get_yahoo_ticker_suffix(country_code).ok_or_else(|| {
+    JobError::Permanent(format!(
+      "unknown yahoo ticker suffix for country {country_code}. set it by environment variable STOCK_TICKER_SUFFIX_{}",
+      country_code.to_uppercase()
+    ))
+  })?;
 
-  // Get US country id from database
-  let country = get_country_by_code(&pg_client, country_code).await?;
-  debug!("got us country id from postgresql database");
+  // Get country id from database
+  let country = get_country_by_code(&pg_client, country_code).await.map_err(JobError::Retryable)?;
+  debug!("got {country_code} country id from postgresql database");
 
-  // Get all supported US stocks in database
-  let supported_stocks = get_all_stocks_by_country_id(&pg_client, &country.id.to_string()).await?;
+  // Get all supported stocks of the country in database
+  let supported_stocks = get_all_stocks_by_country_id(&pg_client, &country.id.to_string())
+    .await
+    .map_err(JobError::Retryable)?;
   debug!("got all supported stocks from postgresql database");
 
+  // Currencies of the stocks to be compared with the quote currencies
+  let currencies = get_all_currencies(&pg_client)
+    .await
+    .map_err(JobError::Retryable)?
+    .into_iter()
+    .map(|c| (c.id, c.ticker))
+    .collect::<HashMap<Uuid, String>>();
+
+  // Fetch quotes of all stocks concurrently within the limits of the yahoo finance api client shared by all cronjobs
+  let mut quote_requests = JoinSet::new();
+  for stock in supported_stocks.iter() {
+    let yahoo_ticker = to_yahoo_ticker(&stock.ticker, &ticker_suffix);
+    let yahoo_finance_client = context.yahoo_finance_client.clone();
+    let stock_id = stock.id;
+    debug!("going to get latest price quote for stock {} as {yahoo_ticker}", stock.ticker);
+    quote_requests.spawn(async move { (stock_id, get_latest_stock_quote(&yahoo_finance_client, &yahoo_ticker).await) });
+  }
+  let mut quotes = HashMap::new();
+  while let Some(result) = quote_requests.join_next().await {
+    let (stock_id, quote) = result?;
+    quotes.insert(stock_id, quote);
+  }
+
+  let mut summary = RunSummary::default();
   for stock in supported_stocks.iter() {
-    debug!("going to get latest price quote for stock {}", stock.ticker);
+    let quote = quotes
+      .remove(&stock.id)
+      .unwrap_or_else(|| Err("latest quote was not requested".to_string()));
+    let (quote_price, quote_currency) = match quote {
+      Ok(quote) => quote,
+      Err(e) => {
+        summary.fail(&stock.ticker, e);
+        continue;
+      }
+    };
 
-    let quote = yahoo_finance_api_client
-      .get_latest_quotes(&stock.ticker, "1d")
+    let stock_currency = currencies.get(&stock.currency_id).cloned().unwrap_or_default();
+    // Keep 2 more decimal places for converted price so that prices of penny stocks are not rounded away
+    let (currency, price) = match get_main_unit_currency(&quote_currency) {
+      Some((currency, divisor)) => (
+        currency.to_string(),
+        Decimal::from_f64(quote_price).map(|price| format!("{:.4}", price / Decimal::from(divisor))),
+      ),
+      None => (
+        quote_currency.clone(),
+        Decimal::from_f64(quote_price).map(|price| format!("{:.2}", price)),
+      ),
+    };
+    let Some(price) = price else {
+      summary.fail(&stock.ticker, format!("invalid latest price {quote_price}"));
+      continue;
+    };
+
+    // Do not write a price which is in another currency than the stock
+    if !currency.eq_ignore_ascii_case(&stock_currency) {
+      summary.skip(
+        &stock.ticker,
+        format!(
+          "latest price {quote_price} is quoted in {quote_currency} but the stock is in {stock_currency}. flagged it instead of updating"
+        ),
+      );
+      flag_stock_price_mismatch(
+        &pg_client,
+        FlagStockPriceMismatchParams {
+          stock_currency,
+          quote_currency,
+          stock_id: stock.id,
+          ticker: stock.ticker.clone(),
+          quote_price: quote_price.to_string(),
+        },
+      )
       .await
-      .map_err(|e| format!("failed to get latest quote for {}. {}", stock.ticker, e))?
-      .last_quote()
-      .map_err(|e| format!("failed to extract last quote from latest quote of {}. {}", stock.ticker, e))?;
+      .map_err(JobError::Retryable)?;
+      continue;
+    }
 
     // Update current price of the target stock in database
     debug!("going to update latest price for stock {}", stock.ticker);
@@ -47,15 +211,86 @@ pub async fn update_latest_stock_prices(country_code: &str) -> Result<(), Box<dy
       &pg_client,
       UpdateStockCurrentPriceParams {
         id: stock.id,
-        current_price: format!("{:.2}", quote.close),
+        current_price: price,
       },
     )
-    .await?;
+    .await
+    .map_err(JobError::Retryable)?;
+    delete_stock_price_mismatch(&pg_client, stock.id)
+      .await
+      .map_err(JobError::Retryable)?;
+    summary.succeed();
     debug!("updated latest price for stock {}", stock.ticker);
   }
+  let summary = summary.into_result()?;
+
+  if let Some(session) = closed_session {
+    update_last_closed_session(
+      &pg_client,
+      UpdateLastClosedSessionParams {
+        country_code: country_code.to_string(),
+        last_closed_session: session,
+      },
+    )
+    .await
+    .map_err(JobError::Retryable)?;
+  }
+
+  Ok(summary)
+}
+
+// Latest price of the ticker with the currency it is quoted in
+async fn get_latest_stock_quote(yahoo_finance_client: &YahooFinanceClient, yahoo_ticker: &str) -> Result<(f64, String), String> {
+  let response = yahoo_finance_client
+    .get_latest_quotes(yahoo_ticker, "1d")
+    .await
+    .map_err(|e| format!("failed to get latest quote. {}", e))?;
+  let quote = response
+    .last_quote()
+    .map_err(|e| format!("failed to extract last quote from latest quote. {}", e))?;
+  let metadata = response
+    .metadata()
+    .map_err(|e| format!("failed to extract metadata from latest quote. {}", e))?;
+
+  Ok((quote.close, metadata.currency))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn appends_suffix_to_ticker_without_it() {
+    assert_eq!(to_yahoo_ticker("0700", ".HK"), "0700.HK");
+    assert_eq!(to_yahoo_ticker("VOD", ".L"), "VOD.L");
+  }
 
-  // End database connection
-  pg_client.close().await;
+  #[test]
+  fn keeps_ticker_already_having_suffix() {
+    assert_eq!(to_yahoo_ticker("0700.HK", ".HK"), "0700.HK");
+    assert_eq!(to_yahoo_ticker("SAP.DE", ".DE"), "SAP.DE");
+  }
+
+  #[test]
+  fn keeps_ticker_of_market_without_suffix() {
+    assert_eq!(to_yahoo_ticker("AAPL", ""), "AAPL");
+    assert_eq!(to_yahoo_ticker("BRK.B", ""), "BRK.B");
+  }
 
-  Ok(())
+  #[test]
+  fn converts_sub_unit_currencies_into_main_unit() {
+    for (sub_unit, main_unit) in [("GBp", "GBP"), ("GBX", "GBP"), ("ZAc", "ZAR"), ("ILA", "ILS")] {
+      let (currency, divisor) = get_main_unit_currency(sub_unit).unwrap();
+
+      assert_eq!(currency, main_unit);
+      assert_eq!(Decimal::from(1234) / Decimal::from(divisor), Decimal::new(1234, 2), "{sub_unit}");
+    }
+  }
+
+  #[test]
+  fn keeps_currencies_already_in_main_unit() {
+    for currency in ["GBP", "USD", "ZAR", "ILS", "HKD"] {
+      assert_eq!(get_main_unit_currency(currency), None);
+    }
+  }
 }
@@ -0,0 +1,145 @@
+use super::retry::JobError;
+use super::stock::{get_main_unit_currency, get_yahoo_ticker_suffix, to_yahoo_ticker};
+use super::summary::RunSummary;
+use super::JobContext;
+use crate::external::db::query::country::get_all_countries_with_stocks;
+use crate::external::db::query::currency::get_all_currencies;
+use crate::external::db::query::stock::get_all_stocks_by_country_id;
+use crate::external::db::query::stock_price_history::{
+  get_latest_stock_price_history_date, upsert_stock_price_history, StockPriceBar, UpsertStockPriceHistoryParams,
+};
+use crate::external::yahoo_finance::YahooFinanceClient;
+use rust_decimal::prelude::FromPrimitive;
+use rust_decimal::Decimal;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use time::{Date, Duration, OffsetDateTime};
+use tracing::{debug, info};
+use uuid::Uuid;
+use yahoo_finance_api::{Quote, YMetaData};
+
+// Recent bars are fetched again as the bar of an ongoing session is incomplete and yahoo finance might revise the others
+const REFETCH_DAYS: i64 = 7;
+
+// Convert a bar quoted by yahoo finance into the main unit currency, dated in the local timezone of its exchange
+fn to_stock_price_bar(quote: &Quote, gmt_offset: i64, divisor: i64) -> Option<StockPriceBar> {
+  let to_price = |price: f64| Decimal::from_f64(price).map(|price| (price / Decimal::from(divisor)).round_dp(4));
+
+  Some(StockPriceBar {
+    date: OffsetDateTime::from_unix_timestamp(quote.timestamp as i64 + gmt_offset)
+      .ok()?
+      .date(),
+    open: to_price(quote.open)?,
+    high: to_price(quote.high)?,
+    low: to_price(quote.low)?,
+    close: to_price(quote.close)?,
+    adjusted_close: to_price(quote.adjclose)?,
+    volume: i64::try_from(quote.volume).ok()?,
+  })
+}
+
+// Record daily bars of every stock since the last recorded bar
+// Stocks without any recorded bar, e.g. newly added ones, get their full history backfilled
+#[tracing::instrument(skip(context))]
+pub async fn record_stock_price_history(context: JobContext) -> Result<RunSummary, Box<dyn Error>> {
+  let pg_client = context.pg_client;
+
+  let currencies = get_all_currencies(&pg_client)
+    .await
+    .map_err(JobError::Retryable)?
+    .into_iter()
+    .map(|c| (c.id, c.ticker))
+    .collect::<HashMap<Uuid, String>>();
+
+  let mut summary = RunSummary::default();
+  for country in get_all_countries_with_stocks(&pg_client)
+    .await
+    .map_err(JobError::Retryable)?
+    .into_iter()
+  {
+    let Some(ticker_suffix) = get_yahoo_ticker_suffix(&country.code) else {
+      summary.skip(&country.code, "unknown yahoo ticker suffix. skipped price history of its stocks");
+      continue;
+    };
+    for stock in get_all_stocks_by_country_id(&pg_client, &country.id.to_string())
+      .await
+      .map_err(JobError::Retryable)?
+      .iter()
+    {
+      let yahoo_ticker = to_yahoo_ticker(&stock.ticker, &ticker_suffix);
+      let latest_date = get_latest_stock_price_history_date(&pg_client, stock.id)
+        .await
+        .map_err(JobError::Retryable)?;
+      let (quotes, metadata) = match get_stock_price_history(&context.yahoo_finance_client, &yahoo_ticker, latest_date).await {
+        Ok(history) => history,
+        Err(e) => {
+          summary.fail(&stock.ticker, e);
+          continue;
+        }
+      };
+
+      let stock_currency = currencies.get(&stock.currency_id).cloned().unwrap_or_default();
+      let (currency, divisor) = get_main_unit_currency(&metadata.currency).unwrap_or((&metadata.currency, 1));
+      if !currency.eq_ignore_ascii_case(&stock_currency) {
+        summary.skip(
+          &stock.ticker,
+          format!(
+            "price history is quoted in {} but the stock is in {stock_currency}",
+            metadata.currency
+          ),
+        );
+        continue;
+      }
+
+      // Yahoo finance might return more than one bar for the ongoing session, in which case the latest one is kept
+      let bars = quotes
+        .iter()
+        .filter_map(|quote| to_stock_price_bar(quote, metadata.gmtoffset as i64, divisor))
+        .map(|bar| (bar.date, bar))
+        .collect::<BTreeMap<_, _>>()
+        .into_values()
+        .collect::<Vec<StockPriceBar>>();
+      if bars.is_empty() {
+        summary.skip(&stock.ticker, "no price history is returned");
+        continue;
+      }
+      let upserted_count = upsert_stock_price_history(&pg_client, UpsertStockPriceHistoryParams { bars, stock_id: stock.id })
+        .await
+        .map_err(JobError::Retryable)?;
+      summary.succeed();
+      debug!("recorded {upserted_count} daily bars of stock {}", stock.ticker);
+    }
+  }
+
+  summary.into_result()
+}
+
+// Daily bars of the ticker since a few days before the latest recorded date, or its full history when nothing is recorded
+async fn get_stock_price_history(
+  yahoo_finance_client: &YahooFinanceClient,
+  yahoo_ticker: &str,
+  latest_date: Option<Date>,
+) -> Result<(Vec<Quote>, YMetaData), String> {
+  let response = match latest_date {
+    Some(latest_date) => {
+      debug!("going to get price history of {yahoo_ticker} since {latest_date}");
+      let start = (latest_date - Duration::days(REFETCH_DAYS)).midnight().assume_utc();
+      yahoo_finance_client
+        .get_quote_history(yahoo_ticker, start, OffsetDateTime::now_utc())
+        .await
+    }
+    None => {
+      info!("going to backfill full price history of {yahoo_ticker}");
+      yahoo_finance_client.get_quote_range(yahoo_ticker, "1d", "max").await
+    }
+  }
+  .map_err(|e| format!("failed to get price history. {}", e))?;
+  let metadata = response
+    .metadata()
+    .map_err(|e| format!("failed to extract metadata from price history. {}", e))?;
+  let quotes = response
+    .quotes()
+    .map_err(|e| format!("failed to extract quotes from price history. {}", e))?;
+
+  Ok((quotes, metadata))
+}
@@ -0,0 +1,82 @@
+use super::retry::JobError;
+use std::error::Error;
+use std::fmt::Display;
+use std::ops::AddAssign;
+use tracing::{debug, warn};
+
+// Outcome of every item processed by a cronjob execution, so that one failing item does not stop the rest of the batch
+// Failed and skipped items are kept as 'item: reason' to be recorded in the job run history
+#[derive(Debug, Default, Clone)]
+pub struct RunSummary {
+  pub succeeded: i64,
+  pub failed: Vec<String>,
+  pub skipped: Vec<String>,
+  // Only reported by cronjobs upserting their items
+  pub written: Option<WriteReport>,
+}
+
+// Number of items upserted into database by their effect
+#[derive(Debug, Default, Clone, Copy)]
+pub struct WriteReport {
+  pub inserted: i64,
+  pub updated: i64,
+  pub unchanged: i64,
+}
+
+impl AddAssign for WriteReport {
+  fn add_assign(&mut self, other: Self) {
+    self.inserted += other.inserted;
+    self.updated += other.updated;
+    self.unchanged += other.unchanged;
+  }
+}
+
+impl RunSummary {
+  pub fn succeed(&mut self) {
+    self.succeeded += 1;
+  }
+
+  pub fn fail(&mut self, item: &str, reason: impl Display) {
+    warn!("failed to process {item}. {reason}");
+    self.failed.push(format!("{item}: {reason}"));
+  }
+
+  pub fn skip(&mut self, item: &str, reason: impl Display) {
+    debug!("skipped {item}. {reason}");
+    self.skipped.push(format!("{item}: {reason}"));
+  }
+
+  pub fn write(&mut self, report: WriteReport) {
+    *self.written.get_or_insert_with(WriteReport::default) += report;
+  }
+
+  // Fail the execution when every item failed, which is more likely an outage than a problem of the items themselves
+  pub fn into_result(self) -> Result<RunSummary, Box<dyn Error>> {
+    if self.succeeded == 0 && !self.failed.is_empty() {
+      return Err(JobError::Retryable(format!("all {} items failed. {}", self.failed.len(), self.failed.join("; "))).into());
+    }
+
+    Ok(self)
+  }
+}
+
+impl AddAssign for RunSummary {
+  fn add_assign(&mut self, other: Self) {
+    self.succeeded += other.succeeded;
+    self.failed.extend(other.failed);
+    self.skipped.extend(other.skipped);
+    if let Some(written) = other.written {
+      self.write(written);
+    }
+  }
+}
+
+// Cronjobs which do not process items one by one only report how many items they processed
+impl From<i64> for RunSummary {
+  fn from(succeeded: i64) -> Self {
+    RunSummary {
+      succeeded,
+      ..Default::default()
+    }
+  }
+}
@@ -1 +1,3 @@
 pub mod db;
+pub mod exchange_rate_provider;
+pub mod yahoo_finance;
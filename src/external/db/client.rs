@@ -21,6 +21,18 @@ pub async fn init_pg() -> Result<Pool<Postgres>, String> {
   return pool;
 }
 
+#[tracing::instrument(skip(pg_client))]
+pub async fn migrate_pg(pg_client: &Pool<Postgres>) -> Result<(), String> {
+  info!("applying postgresql database migrations");
+  sqlx::migrate!()
+    .run(pg_client)
+    .await
+    .map_err(|e| format!("Cannot apply postgresql database migrations. {}", e))?;
+  info!("database migrations applied");
+
+  Ok(())
+}
+
 #[tracing::instrument]
 pub async fn init_mdb() -> Result<Client, String> {
   // Try to get the environment variable 'MONGODB' that stores the mongodb database connection url
@@ -3,6 +3,17 @@ pub mod account_stock;
 pub mod country;
 pub mod currency;
 pub mod exchange_rate;
+pub mod exchange_rate_backfill;
+pub mod exchange_rate_quarantine;
 pub mod future_payment;
+pub mod future_payment_catch_up;
+pub mod future_payment_recurrence;
+pub mod future_payment_transaction;
+pub mod job_run;
+pub mod lock;
+pub mod market_session;
+pub mod paused_cronjob;
 pub mod stock;
+pub mod stock_price_history;
+pub mod stock_price_mismatch;
 pub mod transaction;
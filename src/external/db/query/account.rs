@@ -1,4 +1,4 @@
-use sqlx::{query, query_as, Pool, Postgres};
+use sqlx::{query, query_as, PgExecutor, Postgres, Transaction};
 use uuid::Uuid;
 
 #[derive(Debug)]
@@ -14,8 +14,8 @@ pub struct UpdateAccountBalanceParams {
   pub balance: String,
 }
 
-#[tracing::instrument]
-pub async fn get_account_balance_snapshots(pg_client: &Pool<Postgres>) -> Result<Vec<AccountBalanceSnapshot>, String> {
+#[tracing::instrument(skip(pg_client))]
+pub async fn get_account_balance_snapshots(pg_client: impl PgExecutor<'_>) -> Result<Vec<AccountBalanceSnapshot>, String> {
   query_as!(
     AccountBalanceSnapshot,
     r#"
@@ -28,23 +28,25 @@ pub async fn get_account_balance_snapshots(pg_client: &Pool<Postgres>) -> Result
   .map_err(|e| format!("failed to get account balance snapshots from postgresql database. {}", e))
 }
 
-#[tracing::instrument]
-pub async fn get_account_balance_by_id(pg_client: &Pool<Postgres>, id: Uuid) -> Result<String, String> {
+// Row of the account is locked until the transaction ends, so that the balance cannot be changed by others in between
+// reading it and writing the adjusted balance back
+#[tracing::instrument(skip(pg_transaction))]
+pub async fn get_account_balance_by_id_for_update(pg_transaction: &mut Transaction<'_, Postgres>, id: Uuid) -> Result<String, String> {
   let raw = query!(
     r#"
-      SELECT balance FROM everytrack_backend.account WHERE id = $1
+      SELECT balance FROM everytrack_backend.account WHERE id = $1 FOR UPDATE
     "#,
     id,
   )
-  .fetch_one(pg_client)
+  .fetch_one(&mut **pg_transaction)
   .await
-  .map_err(|e| format!("failed to get account balance from postgresql database. {}", e))?;
+  .map_err(|e| format!("failed to get account balance for update from postgresql database. {}", e))?;
 
   Ok(raw.balance)
 }
 
-#[tracing::instrument]
-pub async fn update_account_balance(pg_client: &Pool<Postgres>, params: UpdateAccountBalanceParams) -> Result<(), String> {
+#[tracing::instrument(skip(pg_client))]
+pub async fn update_account_balance(pg_client: impl PgExecutor<'_>, params: UpdateAccountBalanceParams) -> Result<(), String> {
   let rows_affected = query!(
     r#"
       UPDATE everytrack_backend.account SET balance = $1 WHERE id = $2
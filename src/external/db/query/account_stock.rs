@@ -1,4 +1,4 @@
-use sqlx::{query_as, Pool, Postgres};
+use sqlx::{query_as, PgExecutor};
 use uuid::Uuid;
 
 #[derive(Debug)]
@@ -9,9 +9,9 @@ pub struct AccountStockHoldingBalanceSnapshot {
   pub current_price: String,
 }
 
-#[tracing::instrument]
+#[tracing::instrument(skip(db_client))]
 pub async fn get_account_stock_holding_balance_snapshots(
-  db_client: &Pool<Postgres>,
+  db_client: impl PgExecutor<'_>,
 ) -> Result<Vec<AccountStockHoldingBalanceSnapshot>, String> {
   query_as!(
     AccountStockHoldingBalanceSnapshot,
@@ -1,4 +1,4 @@
-use sqlx::{query_as, Pool, Postgres};
+use sqlx::{query_as, PgExecutor};
 use uuid::Uuid;
 
 #[derive(Debug)]
@@ -8,8 +8,8 @@ pub struct Country {
   pub code: String,
 }
 
-#[tracing::instrument]
-pub async fn get_country_by_code(pg_client: &Pool<Postgres>, code: &str) -> Result<Country, String> {
+#[tracing::instrument(skip(pg_client))]
+pub async fn get_country_by_code(pg_client: impl PgExecutor<'_>, code: &str) -> Result<Country, String> {
   query_as!(
     Country,
     r#"
@@ -22,3 +22,18 @@ pub async fn get_country_by_code(pg_client: &Pool<Postgres>, code: &str) -> Resu
   .await
   .map_err(|e| format!("failed to get country by code from postgresql database. {}", e))
 }
+
+#[tracing::instrument(skip(pg_client))]
+pub async fn get_all_countries_with_stocks(pg_client: impl PgExecutor<'_>) -> Result<Vec<Country>, String> {
+  query_as!(
+    Country,
+    r#"
+      SELECT id, name, code FROM everytrack_backend.country AS c
+      WHERE EXISTS (SELECT 1 FROM everytrack_backend.stock AS s WHERE s.country_id = c.id)
+      ORDER BY code
+    "#,
+  )
+  .fetch_all(pg_client)
+  .await
+  .map_err(|e| format!("failed to get all countries with stocks from postgresql database. {}", e))
+}
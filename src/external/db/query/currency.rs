@@ -1,4 +1,4 @@
-use sqlx::{query_as, Pool, Postgres};
+use sqlx::{query_as, PgExecutor};
 use uuid::Uuid;
 
 #[derive(Debug)]
@@ -8,8 +8,8 @@ pub struct Currency {
   pub symbol: String,
 }
 
-#[tracing::instrument]
-pub async fn get_all_currencies(db_client: &Pool<Postgres>) -> Result<Vec<Currency>, String> {
+#[tracing::instrument(skip(db_client))]
+pub async fn get_all_currencies(db_client: impl PgExecutor<'_>) -> Result<Vec<Currency>, String> {
   query_as!(
     Currency,
     r#"
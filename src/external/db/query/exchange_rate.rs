@@ -1,6 +1,13 @@
-use sqlx::{query, query_scalar, Pool, Postgres};
+use sqlx::{query, query_as, query_scalar, PgExecutor};
 use uuid::Uuid;
 
+#[derive(Debug)]
+pub struct ExchangeRate {
+  pub base_currency_id: Option<Uuid>,
+  pub target_currency_id: Option<Uuid>,
+  pub rate: String,
+}
+
 #[derive(Debug)]
 pub struct CheckExistingExchangeRateParams {
   pub base_currency_id: Uuid,
@@ -21,8 +28,15 @@ pub struct UpdateExchangeRateParams {
   pub target_currency_id: Uuid,
 }
 
-#[tracing::instrument]
-pub async fn check_existing_exchange_rate(pg_client: &Pool<Postgres>, params: CheckExistingExchangeRateParams) -> Result<bool, String> {
+#[derive(Debug)]
+pub struct UpsertExchangeRateSourceParams {
+  pub base_currency_id: Uuid,
+  pub target_currency_id: Uuid,
+  pub provider: String,
+}
+
+#[tracing::instrument(skip(pg_client))]
+pub async fn check_existing_exchange_rate(pg_client: impl PgExecutor<'_>, params: CheckExistingExchangeRateParams) -> Result<bool, String> {
   let is_exchange_rate_record_exists = query_scalar!(
     r#"
       SELECT EXISTS(
@@ -40,8 +54,8 @@ pub async fn check_existing_exchange_rate(pg_client: &Pool<Postgres>, params: Ch
   is_exchange_rate_record_exists.and_then(|r| r.ok_or("unexpected error occured when checking if exchange rate record exists".to_string()))
 }
 
-#[tracing::instrument]
-pub async fn create_new_exchange_rate(pg_client: &Pool<Postgres>, params: CreateNewExchangeRateParams) -> Result<(), String> {
+#[tracing::instrument(skip(pg_client))]
+pub async fn create_new_exchange_rate(pg_client: impl PgExecutor<'_>, params: CreateNewExchangeRateParams) -> Result<(), String> {
   let rows_affected = query!(
     r#"
       INSERT INTO everytrack_backend.exchange_rate (base_currency_id, target_currency_id, rate)
@@ -63,8 +77,8 @@ pub async fn create_new_exchange_rate(pg_client: &Pool<Postgres>, params: Create
   }
 }
 
-#[tracing::instrument]
-pub async fn update_exchange_rate(pg_client: &Pool<Postgres>, params: UpdateExchangeRateParams) -> Result<(), String> {
+#[tracing::instrument(skip(pg_client))]
+pub async fn update_exchange_rate(pg_client: impl PgExecutor<'_>, params: UpdateExchangeRateParams) -> Result<(), String> {
   let rows_affected = query!(
     r#"
       UPDATE everytrack_backend.exchange_rate
@@ -86,3 +100,41 @@ pub async fn update_exchange_rate(pg_client: &Pool<Postgres>, params: UpdateExch
     Err("unexpected error occured when updating exchange rate in postgresql database".to_string())
   }
 }
+
+#[tracing::instrument(skip(pg_client))]
+pub async fn upsert_exchange_rate_source(pg_client: impl PgExecutor<'_>, params: UpsertExchangeRateSourceParams) -> Result<(), String> {
+  let rows_affected = query!(
+    r#"
+      INSERT INTO everytrack_cron.exchange_rate_source (base_currency_id, target_currency_id, provider, updated_at)
+      VALUES ($1, $2, $3, NOW())
+      ON CONFLICT (base_currency_id, target_currency_id) DO UPDATE SET provider = EXCLUDED.provider, updated_at = EXCLUDED.updated_at
+    "#,
+    params.base_currency_id,
+    params.target_currency_id,
+    params.provider,
+  )
+  .execute(pg_client)
+  .await
+  .map_err(|e| format!("failed to upsert exchange rate source in postgresql database. {}", e))?
+  .rows_affected();
+
+  if rows_affected.ge(&0) {
+    Ok(())
+  } else {
+    Err("unexpected error occured when upserting exchange rate source in postgresql database".to_string())
+  }
+}
+
+#[tracing::instrument(skip(pg_client))]
+pub async fn get_all_exchange_rates(pg_client: impl PgExecutor<'_>) -> Result<Vec<ExchangeRate>, String> {
+  query_as!(
+    ExchangeRate,
+    r#"
+      SELECT base_currency_id, target_currency_id, rate
+      FROM everytrack_backend.exchange_rate
+    "#,
+  )
+  .fetch_all(pg_client)
+  .await
+  .map_err(|e| format!("failed to get all exchange rates from postgresql database. {}", e))
+}
@@ -0,0 +1,141 @@
+use sqlx::{query, query_as, PgExecutor};
+use time::{Date, OffsetDateTime};
+use uuid::Uuid;
+
+#[derive(Debug)]
+pub struct ExchangeRateBackfill {
+  pub id: Uuid,
+  pub start_date: Date,
+  pub end_date: Date,
+  pub status: String,
+  pub error: Option<String>,
+  pub total_days: i32,
+  pub completed_days: i32,
+  pub skipped_days: i32,
+  pub last_completed_date: Option<Date>,
+  pub created_at: OffsetDateTime,
+  pub updated_at: OffsetDateTime,
+}
+
+#[derive(Debug)]
+pub struct CreateNewExchangeRateBackfillParams {
+  pub id: Uuid,
+  pub start_date: Date,
+  pub end_date: Date,
+  pub status: String,
+  pub total_days: i32,
+}
+
+#[derive(Debug)]
+pub struct UpdateExchangeRateBackfillProgressParams {
+  pub id: Uuid,
+  pub completed_days: i32,
+  pub skipped_days: i32,
+  pub last_completed_date: Date,
+}
+
+#[derive(Debug)]
+pub struct UpdateExchangeRateBackfillStatusParams {
+  pub id: Uuid,
+  pub status: String,
+  pub error: Option<String>,
+}
+
+#[tracing::instrument(skip(pg_client))]
+pub async fn create_new_exchange_rate_backfill(
+  pg_client: impl PgExecutor<'_>,
+  params: CreateNewExchangeRateBackfillParams,
+) -> Result<(), String> {
+  let rows_affected = query!(
+    r#"
+      INSERT INTO everytrack_cron.exchange_rate_backfill (id, start_date, end_date, status, total_days, created_at, updated_at)
+      VALUES ($1, $2, $3, $4, $5, NOW(), NOW())
+    "#,
+    params.id,
+    params.start_date,
+    params.end_date,
+    params.status,
+    params.total_days,
+  )
+  .execute(pg_client)
+  .await
+  .map_err(|e| format!("failed to create new exchange rate backfill in postgresql database. {}", e))?
+  .rows_affected();
+
+  if rows_affected.ge(&0) {
+    Ok(())
+  } else {
+    Err("unexpected error occured when creating new exchange rate backfill in postgresql database".to_string())
+  }
+}
+
+#[tracing::instrument(skip(pg_client))]
+pub async fn update_exchange_rate_backfill_progress(
+  pg_client: impl PgExecutor<'_>,
+  params: UpdateExchangeRateBackfillProgressParams,
+) -> Result<(), String> {
+  let rows_affected = query!(
+    r#"
+      UPDATE everytrack_cron.exchange_rate_backfill
+      SET completed_days = $1, skipped_days = $2, last_completed_date = $3, updated_at = NOW()
+      WHERE id = $4
+    "#,
+    params.completed_days,
+    params.skipped_days,
+    params.last_completed_date,
+    params.id,
+  )
+  .execute(pg_client)
+  .await
+  .map_err(|e| format!("failed to update exchange rate backfill progress in postgresql database. {}", e))?
+  .rows_affected();
+
+  if rows_affected.ge(&0) {
+    Ok(())
+  } else {
+    Err("unexpected error occured when updating exchange rate backfill progress in postgresql database".to_string())
+  }
+}
+
+#[tracing::instrument(skip(pg_client))]
+pub async fn update_exchange_rate_backfill_status(
+  pg_client: impl PgExecutor<'_>,
+  params: UpdateExchangeRateBackfillStatusParams,
+) -> Result<(), String> {
+  let rows_affected = query!(
+    r#"
+      UPDATE everytrack_cron.exchange_rate_backfill
+      SET status = $1, error = $2, updated_at = NOW()
+      WHERE id = $3
+    "#,
+    params.status,
+    params.error,
+    params.id,
+  )
+  .execute(pg_client)
+  .await
+  .map_err(|e| format!("failed to update exchange rate backfill status in postgresql database. {}", e))?
+  .rows_affected();
+
+  if rows_affected.ge(&0) {
+    Ok(())
+  } else {
+    Err("unexpected error occured when updating exchange rate backfill status in postgresql database".to_string())
+  }
+}
+
+#[tracing::instrument(skip(pg_client))]
+pub async fn get_exchange_rate_backfill_by_id(pg_client: impl PgExecutor<'_>, id: Uuid) -> Result<Option<ExchangeRateBackfill>, String> {
+  query_as!(
+    ExchangeRateBackfill,
+    r#"
+      SELECT id, start_date, end_date, status, error, total_days, completed_days, skipped_days, last_completed_date, created_at, updated_at
+      FROM everytrack_cron.exchange_rate_backfill
+      WHERE id = $1
+    "#,
+    id,
+  )
+  .fetch_optional(pg_client)
+  .await
+  .map_err(|e| format!("failed to get exchange rate backfill by id from postgresql database. {}", e))
+}
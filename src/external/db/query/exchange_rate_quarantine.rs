@@ -0,0 +1,153 @@
+use sqlx::{query, query_as, PgExecutor};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+#[derive(Debug)]
+pub struct ExchangeRateQuarantine {
+  pub id: Uuid,
+  pub base_currency_id: Uuid,
+  pub target_currency_id: Uuid,
+  pub rate: String,
+  pub previous_rate: Option<String>,
+  pub provider: String,
+  pub reason: String,
+  pub status: String,
+  pub created_at: OffsetDateTime,
+  pub updated_at: OffsetDateTime,
+}
+
+#[derive(Debug)]
+pub struct QuarantineExchangeRateParams {
+  pub id: Uuid,
+  pub base_currency_id: Uuid,
+  pub target_currency_id: Uuid,
+  pub rate: String,
+  pub previous_rate: Option<String>,
+  pub provider: String,
+  pub reason: String,
+}
+
+#[derive(Debug)]
+pub struct UpdateExchangeRateQuarantineStatusParams {
+  pub id: Uuid,
+  pub status: String,
+}
+
+#[derive(Debug)]
+pub struct SupersedePendingExchangeRateQuarantineParams {
+  pub base_currency_id: Uuid,
+  pub target_currency_id: Uuid,
+}
+
+// Replace the pending quarantine of the same currency pair if there is one
+#[tracing::instrument(skip(pg_client))]
+pub async fn quarantine_exchange_rate(pg_client: impl PgExecutor<'_>, params: QuarantineExchangeRateParams) -> Result<(), String> {
+  let rows_affected = query!(
+    r#"
+      INSERT INTO everytrack_cron.exchange_rate_quarantine
+        (id, base_currency_id, target_currency_id, rate, previous_rate, provider, reason, status, created_at, updated_at)
+      VALUES ($1, $2, $3, $4, $5, $6, $7, 'pending', NOW(), NOW())
+      ON CONFLICT (base_currency_id, target_currency_id) WHERE status = 'pending'
+      DO UPDATE SET rate = EXCLUDED.rate, previous_rate = EXCLUDED.previous_rate, provider = EXCLUDED.provider, reason = EXCLUDED.reason, updated_at = EXCLUDED.updated_at
+    "#,
+    params.id,
+    params.base_currency_id,
+    params.target_currency_id,
+    params.rate,
+    params.previous_rate,
+    params.provider,
+    params.reason,
+  )
+  .execute(pg_client)
+  .await
+  .map_err(|e| format!("failed to quarantine exchange rate in postgresql database. {}", e))?
+  .rows_affected();
+
+  if rows_affected.ge(&0) {
+    Ok(())
+  } else {
+    Err("unexpected error occured when quarantining exchange rate in postgresql database".to_string())
+  }
+}
+
+#[tracing::instrument(skip(pg_client))]
+pub async fn get_pending_exchange_rate_quarantines(pg_client: impl PgExecutor<'_>) -> Result<Vec<ExchangeRateQuarantine>, String> {
+  query_as!(
+    ExchangeRateQuarantine,
+    r#"
+      SELECT id, base_currency_id, target_currency_id, rate, previous_rate, provider, reason, status, created_at, updated_at
+      FROM everytrack_cron.exchange_rate_quarantine
+      WHERE status = 'pending'
+      ORDER BY updated_at DESC
+    "#,
+  )
+  .fetch_all(pg_client)
+  .await
+  .map_err(|e| format!("failed to get pending exchange rate quarantines from postgresql database. {}", e))
+}
+
+#[tracing::instrument(skip(pg_client))]
+pub async fn get_exchange_rate_quarantine_by_id(
+  pg_client: impl PgExecutor<'_>,
+  id: Uuid,
+) -> Result<Option<ExchangeRateQuarantine>, String> {
+  query_as!(
+    ExchangeRateQuarantine,
+    r#"
+      SELECT id, base_currency_id, target_currency_id, rate, previous_rate, provider, reason, status, created_at, updated_at
+      FROM everytrack_cron.exchange_rate_quarantine
+      WHERE id = $1
+    "#,
+    id,
+  )
+  .fetch_optional(pg_client)
+  .await
+  .map_err(|e| format!("failed to get exchange rate quarantine by id from postgresql database. {}", e))
+}
+
+// Only a pending quarantine is updated, returns false if it has been approved or superseded in the meantime
+#[tracing::instrument(skip(pg_client))]
+pub async fn update_exchange_rate_quarantine_status(
+  pg_client: impl PgExecutor<'_>,
+  params: UpdateExchangeRateQuarantineStatusParams,
+) -> Result<bool, String> {
+  let rows_affected = query!(
+    r#"
+      UPDATE everytrack_cron.exchange_rate_quarantine
+      SET status = $1, updated_at = NOW()
+      WHERE id = $2 AND status = 'pending'
+    "#,
+    params.status,
+    params.id,
+  )
+  .execute(pg_client)
+  .await
+  .map_err(|e| format!("failed to update exchange rate quarantine status in postgresql database. {}", e))?
+  .rows_affected();
+
+  Ok(rows_affected > 0)
+}
+
+// Mark the pending quarantine of currency pair as superseded once a newer rate of the pair passed the checks,
+// returns false if there is no pending quarantine of the pair
+#[tracing::instrument(skip(pg_client))]
+pub async fn supersede_pending_exchange_rate_quarantine(
+  pg_client: impl PgExecutor<'_>,
+  params: SupersedePendingExchangeRateQuarantineParams,
+) -> Result<bool, String> {
+  let rows_affected = query!(
+    r#"
+      UPDATE everytrack_cron.exchange_rate_quarantine
+      SET status = 'superseded', updated_at = NOW()
+      WHERE base_currency_id = $1 AND target_currency_id = $2 AND status = 'pending'
+    "#,
+    params.base_currency_id,
+    params.target_currency_id,
+  )
+  .execute(pg_client)
+  .await
+  .map_err(|e| format!("failed to supersede pending exchange rate quarantine in postgresql database. {}", e))?
+  .rows_affected();
+
+  Ok(rows_affected > 0)
+}
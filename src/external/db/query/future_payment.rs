@@ -1,4 +1,4 @@
-use sqlx::{query, query_as, Pool, Postgres};
+use sqlx::{query, query_as, PgExecutor};
 use time::OffsetDateTime;
 use uuid::Uuid;
 
@@ -24,8 +24,8 @@ pub struct UpdateFuturePaymentScheduleParams {
   pub scheduled_at: OffsetDateTime,
 }
 
-#[tracing::instrument]
-pub async fn get_all_future_payments(pg_client: &Pool<Postgres>) -> Result<Vec<FuturePayment>, String> {
+#[tracing::instrument(skip(pg_client))]
+pub async fn get_all_future_payments(pg_client: impl PgExecutor<'_>) -> Result<Vec<FuturePayment>, String> {
   query_as!(
     FuturePayment,
     r#"
@@ -43,8 +43,11 @@ pub async fn get_all_future_payments(pg_client: &Pool<Postgres>) -> Result<Vec<F
   })
 }
 
-#[tracing::instrument]
-pub async fn update_future_payment_schedule(pg_client: &Pool<Postgres>, params: UpdateFuturePaymentScheduleParams) -> Result<(), String> {
+#[tracing::instrument(skip(pg_client))]
+pub async fn update_future_payment_schedule(
+  pg_client: impl PgExecutor<'_>,
+  params: UpdateFuturePaymentScheduleParams,
+) -> Result<(), String> {
   let rows_affected = query!(
     r#"
       UPDATE everytrack_backend.future_payment
@@ -66,8 +69,8 @@ pub async fn update_future_payment_schedule(pg_client: &Pool<Postgres>, params:
   }
 }
 
-#[tracing::instrument]
-pub async fn delete_future_payment(pg_client: &Pool<Postgres>, id: Uuid) -> Result<(), String> {
+#[tracing::instrument(skip(pg_client))]
+pub async fn delete_future_payment(pg_client: impl PgExecutor<'_>, id: Uuid) -> Result<(), String> {
   let rows_affected = query!(
     r#"
       DELETE FROM everytrack_backend.future_payment WHERE id = $1
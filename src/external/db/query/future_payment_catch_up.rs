@@ -0,0 +1,44 @@
+use sqlx::{query, PgExecutor};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+#[derive(Debug)]
+pub struct UpsertFuturePaymentCatchUpParams {
+  pub id: Uuid,
+  pub future_payment_id: Uuid,
+  pub occurrences: i32,
+  pub first_occurrence_at: OffsetDateTime,
+  pub last_occurrence_at: OffsetDateTime,
+  pub capped: bool,
+}
+
+// Create the catch up or extend it to the latest settled occurrence
+#[tracing::instrument(skip(pg_client))]
+pub async fn upsert_future_payment_catch_up(
+  pg_client: impl PgExecutor<'_>,
+  params: UpsertFuturePaymentCatchUpParams,
+) -> Result<(), String> {
+  let rows_affected = query!(
+    r#"
+      INSERT INTO everytrack_cron.future_payment_catch_up (id, future_payment_id, occurrences, first_occurrence_at, last_occurrence_at, capped, caught_up_at)
+      VALUES ($1, $2, $3, $4, $5, $6, NOW())
+      ON CONFLICT (id) DO UPDATE SET occurrences = EXCLUDED.occurrences, last_occurrence_at = EXCLUDED.last_occurrence_at, caught_up_at = EXCLUDED.caught_up_at
+    "#,
+    params.id,
+    params.future_payment_id,
+    params.occurrences,
+    params.first_occurrence_at,
+    params.last_occurrence_at,
+    params.capped,
+  )
+  .execute(pg_client)
+  .await
+  .map_err(|e| format!("failed to upsert future payment catch up in postgresql database. {}", e))?
+  .rows_affected();
+
+  if rows_affected.ge(&0) {
+    Ok(())
+  } else {
+    Err("unexpected error occured when upserting future payment catch up in postgresql database".to_string())
+  }
+}
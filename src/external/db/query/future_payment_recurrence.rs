@@ -0,0 +1,61 @@
+use sqlx::{query, query_as, PgExecutor};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+#[derive(Debug)]
+pub struct FuturePaymentRecurrence {
+  pub anchored_at: OffsetDateTime,
+  pub frequency: i64,
+}
+
+#[derive(Debug)]
+pub struct UpsertFuturePaymentRecurrenceParams {
+  pub future_payment_id: Uuid,
+  pub anchored_at: OffsetDateTime,
+  pub frequency: i64,
+}
+
+#[tracing::instrument(skip(pg_client))]
+pub async fn get_future_payment_recurrence(
+  pg_client: impl PgExecutor<'_>,
+  future_payment_id: Uuid,
+) -> Result<Option<FuturePaymentRecurrence>, String> {
+  query_as!(
+    FuturePaymentRecurrence,
+    r#"
+      SELECT anchored_at, frequency FROM everytrack_cron.future_payment_recurrence
+      WHERE future_payment_id = $1
+    "#,
+    future_payment_id,
+  )
+  .fetch_optional(pg_client)
+  .await
+  .map_err(|e| format!("failed to get future payment recurrence from postgresql database. {}", e))
+}
+
+#[tracing::instrument(skip(pg_client))]
+pub async fn upsert_future_payment_recurrence(
+  pg_client: impl PgExecutor<'_>,
+  params: UpsertFuturePaymentRecurrenceParams,
+) -> Result<(), String> {
+  let rows_affected = query!(
+    r#"
+      INSERT INTO everytrack_cron.future_payment_recurrence (future_payment_id, anchored_at, frequency, updated_at)
+      VALUES ($1, $2, $3, NOW())
+      ON CONFLICT (future_payment_id) DO UPDATE SET anchored_at = EXCLUDED.anchored_at, frequency = EXCLUDED.frequency, updated_at = EXCLUDED.updated_at
+    "#,
+    params.future_payment_id,
+    params.anchored_at,
+    params.frequency,
+  )
+  .execute(pg_client)
+  .await
+  .map_err(|e| format!("failed to upsert future payment recurrence into postgresql database. {}", e))?
+  .rows_affected();
+
+  if rows_affected.ge(&0) {
+    Ok(())
+  } else {
+    Err("unexpected error occured when upserting future payment recurrence into postgresql database".to_string())
+  }
+}
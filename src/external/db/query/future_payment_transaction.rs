@@ -0,0 +1,35 @@
+use sqlx::{query, PgExecutor};
+use time::Date;
+use uuid::Uuid;
+
+#[derive(Debug)]
+pub struct CreateNewFuturePaymentTransactionParams {
+  pub future_payment_id: Uuid,
+  pub occurrence_date: Date,
+  pub transaction_id: Uuid,
+}
+
+// Claim the idempotency key of the occurrence for the transaction
+// Returns false without creating anything if the occurrence has already been settled
+#[tracing::instrument(skip(pg_client))]
+pub async fn create_new_future_payment_transaction(
+  pg_client: impl PgExecutor<'_>,
+  params: CreateNewFuturePaymentTransactionParams,
+) -> Result<bool, String> {
+  let rows_affected = query!(
+    r#"
+      INSERT INTO everytrack_cron.future_payment_transaction (future_payment_id, occurrence_date, transaction_id, created_at)
+      VALUES ($1, $2, $3, NOW())
+      ON CONFLICT (future_payment_id, occurrence_date) DO NOTHING
+    "#,
+    params.future_payment_id,
+    params.occurrence_date,
+    params.transaction_id,
+  )
+  .execute(pg_client)
+  .await
+  .map_err(|e| format!("failed to create new future payment transaction in postgresql database. {}", e))?
+  .rows_affected();
+
+  Ok(rows_affected.gt(&0))
+}
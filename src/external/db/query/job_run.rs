@@ -0,0 +1,186 @@
+use sqlx::{query, query_as, query_scalar, PgExecutor};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+#[derive(Debug)]
+pub struct JobRun {
+  pub id: Uuid,
+  pub job_name: String,
+  pub status: String,
+  pub error: Option<String>,
+  pub items_processed: Option<i64>,
+  pub failed_items: Vec<String>,
+  pub skipped_items: Vec<String>,
+  pub inserted_items: Option<i64>,
+  pub updated_items: Option<i64>,
+  pub unchanged_items: Option<i64>,
+  pub attempts: i32,
+  pub duration_ms: Option<i64>,
+  pub scheduled_at: OffsetDateTime,
+  pub started_at: OffsetDateTime,
+  pub finished_at: Option<OffsetDateTime>,
+}
+
+#[derive(Debug)]
+pub struct CreateNewJobRunParams {
+  pub id: Uuid,
+  pub instance_id: Uuid,
+  pub job_name: String,
+  pub status: String,
+  pub scheduled_at: OffsetDateTime,
+  pub started_at: OffsetDateTime,
+}
+
+#[derive(Debug)]
+pub struct FinishJobRunParams {
+  pub id: Uuid,
+  pub status: String,
+  pub error: Option<String>,
+  pub items_processed: Option<i64>,
+  pub failed_items: Vec<String>,
+  pub skipped_items: Vec<String>,
+  pub inserted_items: Option<i64>,
+  pub updated_items: Option<i64>,
+  pub unchanged_items: Option<i64>,
+  pub attempts: i32,
+  pub duration_ms: i64,
+  pub finished_at: OffsetDateTime,
+}
+
+#[derive(Debug)]
+pub struct InterruptRunningJobRunsParams {
+  pub instance_id: Option<Uuid>,
+  pub status: String,
+  pub error: String,
+}
+
+#[tracing::instrument(skip(pg_client))]
+pub async fn create_new_job_run(pg_client: impl PgExecutor<'_>, params: CreateNewJobRunParams) -> Result<(), String> {
+  let rows_affected = query!(
+    r#"
+      INSERT INTO everytrack_cron.job_run (id, instance_id, job_name, status, scheduled_at, started_at)
+      VALUES ($1, $2, $3, $4, $5, $6)
+    "#,
+    params.id,
+    params.instance_id,
+    params.job_name,
+    params.status,
+    params.scheduled_at,
+    params.started_at,
+  )
+  .execute(pg_client)
+  .await
+  .map_err(|e| format!("failed to create new job run in postgresql database. {}", e))?
+  .rows_affected();
+
+  if rows_affected.ge(&0) {
+    Ok(())
+  } else {
+    Err("unexpected error occured when creating new job run in postgresql database".to_string())
+  }
+}
+
+#[tracing::instrument(skip(pg_client))]
+pub async fn finish_job_run(pg_client: impl PgExecutor<'_>, params: FinishJobRunParams) -> Result<(), String> {
+  let rows_affected = query!(
+    r#"
+      UPDATE everytrack_cron.job_run
+      SET status = $1, error = $2, items_processed = $3, failed_items = $4, skipped_items = $5, inserted_items = $6, updated_items = $7,
+      unchanged_items = $8, attempts = $9, duration_ms = $10, finished_at = $11
+      WHERE id = $12
+    "#,
+    params.status,
+    params.error,
+    params.items_processed,
+    &params.failed_items,
+    &params.skipped_items,
+    params.inserted_items,
+    params.updated_items,
+    params.unchanged_items,
+    params.attempts,
+    params.duration_ms,
+    params.finished_at,
+    params.id,
+  )
+  .execute(pg_client)
+  .await
+  .map_err(|e| format!("failed to finish job run in postgresql database. {}", e))?
+  .rows_affected();
+
+  if rows_affected.ge(&0) {
+    Ok(())
+  } else {
+    Err("unexpected error occured when finishing job run in postgresql database".to_string())
+  }
+}
+
+#[tracing::instrument(skip(pg_client))]
+pub async fn delete_job_run(pg_client: impl PgExecutor<'_>, id: Uuid) -> Result<(), String> {
+  query!(
+    r#"
+      DELETE FROM everytrack_cron.job_run
+      WHERE id = $1
+    "#,
+    id,
+  )
+  .execute(pg_client)
+  .await
+  .map_err(|e| format!("failed to delete job run from postgresql database. {}", e))?;
+
+  Ok(())
+}
+
+// Instances having job runs marked as running, which is none for the runs recorded before instances were tracked
+#[tracing::instrument(skip(pg_client))]
+pub async fn get_instances_with_running_job_runs(pg_client: impl PgExecutor<'_>) -> Result<Vec<Option<Uuid>>, String> {
+  query_scalar!(
+    r#"
+      SELECT DISTINCT instance_id
+      FROM everytrack_cron.job_run
+      WHERE status = 'running'
+    "#,
+  )
+  .fetch_all(pg_client)
+  .await
+  .map_err(|e| format!("failed to get instances with running job runs from postgresql database. {}", e))
+}
+
+// Returns the number of job runs marked as interrupted
+#[tracing::instrument(skip(pg_client))]
+pub async fn interrupt_running_job_runs_of_instance(
+  pg_client: impl PgExecutor<'_>,
+  params: InterruptRunningJobRunsParams,
+) -> Result<u64, String> {
+  let rows_affected = query!(
+    r#"
+      UPDATE everytrack_cron.job_run
+      SET status = $1, error = $2, finished_at = NOW()
+      WHERE status = 'running' AND instance_id IS NOT DISTINCT FROM $3
+    "#,
+    params.status,
+    params.error,
+    params.instance_id,
+  )
+  .execute(pg_client)
+  .await
+  .map_err(|e| format!("failed to interrupt running job runs in postgresql database. {}", e))?
+  .rows_affected();
+
+  Ok(rows_affected)
+}
+
+#[tracing::instrument(skip(pg_client))]
+pub async fn get_latest_job_runs(pg_client: impl PgExecutor<'_>) -> Result<Vec<JobRun>, String> {
+  query_as!(
+    JobRun,
+    r#"
+      SELECT DISTINCT ON (job_name) id, job_name, status, error, items_processed, failed_items, skipped_items, inserted_items, updated_items,
+      unchanged_items, attempts, duration_ms, scheduled_at, started_at, finished_at
+      FROM everytrack_cron.job_run
+      ORDER BY job_name, started_at DESC
+    "#,
+  )
+  .fetch_all(pg_client)
+  .await
+  .map_err(|e| format!("failed to get latest job runs from postgresql database. {}", e))
+}
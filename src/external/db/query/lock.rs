@@ -0,0 +1,38 @@
+use sqlx::{query_scalar, PgExecutor};
+
+// Session level advisory lock is held until it is released or the session ends,
+// so it should be taken on a dedicated connection rather than one returned to the pool
+#[tracing::instrument(skip(pg_client))]
+pub async fn try_acquire_advisory_lock(pg_client: impl PgExecutor<'_>, key: &str) -> Result<bool, String> {
+  let is_lock_acquired = query_scalar!(
+    r#"
+      SELECT pg_try_advisory_lock(hashtextextended($1, 0))
+    "#,
+    key,
+  )
+  .fetch_one(pg_client)
+  .await
+  .map_err(|e| format!("failed to acquire advisory lock in postgresql database. {}", e));
+
+  is_lock_acquired.and_then(|r| r.ok_or("unexpected error occured when acquiring advisory lock".to_string()))
+}
+
+// Whether the session of the connection still holds the advisory lock, a bigint key is split into classid and objid
+#[tracing::instrument(skip(pg_client))]
+pub async fn is_advisory_lock_held(pg_client: impl PgExecutor<'_>, key: &str) -> Result<bool, String> {
+  let is_lock_held = query_scalar!(
+    r#"
+      SELECT EXISTS (
+        SELECT 1 FROM pg_locks
+        WHERE locktype = 'advisory' AND granted AND pid = pg_backend_pid() AND objsubid = 1
+        AND ((classid::bigint << 32) | objid::bigint) = hashtextextended($1, 0)
+      )
+    "#,
+    key,
+  )
+  .fetch_one(pg_client)
+  .await
+  .map_err(|e| format!("failed to check advisory lock in postgresql database. {}", e));
+
+  is_lock_held.and_then(|r| r.ok_or("unexpected error occured when checking advisory lock".to_string()))
+}
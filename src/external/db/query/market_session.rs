@@ -0,0 +1,45 @@
+use sqlx::{query, query_scalar, PgExecutor};
+use time::Date;
+
+#[derive(Debug)]
+pub struct UpdateLastClosedSessionParams {
+  pub country_code: String,
+  pub last_closed_session: Date,
+}
+
+#[tracing::instrument(skip(pg_client))]
+pub async fn get_last_closed_session(pg_client: impl PgExecutor<'_>, country_code: &str) -> Result<Option<Date>, String> {
+  query_scalar!(
+    r#"
+      SELECT last_closed_session FROM everytrack_cron.market_session
+      WHERE country_code = $1
+    "#,
+    country_code,
+  )
+  .fetch_optional(pg_client)
+  .await
+  .map_err(|e| format!("failed to get last closed session of market from postgresql database. {}", e))
+}
+
+#[tracing::instrument(skip(pg_client))]
+pub async fn update_last_closed_session(pg_client: impl PgExecutor<'_>, params: UpdateLastClosedSessionParams) -> Result<(), String> {
+  let rows_affected = query!(
+    r#"
+      INSERT INTO everytrack_cron.market_session (country_code, last_closed_session, updated_at)
+      VALUES ($1, $2, NOW())
+      ON CONFLICT (country_code) DO UPDATE SET last_closed_session = EXCLUDED.last_closed_session, updated_at = EXCLUDED.updated_at
+    "#,
+    params.country_code,
+    params.last_closed_session,
+  )
+  .execute(pg_client)
+  .await
+  .map_err(|e| format!("failed to update last closed session of market in postgresql database. {}", e))?
+  .rows_affected();
+
+  if rows_affected.ge(&0) {
+    Ok(())
+  } else {
+    Err("unexpected error occured when updating last closed session of market in postgresql database".to_string())
+  }
+}
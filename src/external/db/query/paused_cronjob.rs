@@ -0,0 +1,68 @@
+use sqlx::{query, query_scalar, PgExecutor};
+
+// Pausing a paused cronjob keeps the time it was first paused
+#[tracing::instrument(skip(pg_client))]
+pub async fn pause_cronjob(pg_client: impl PgExecutor<'_>, job_name: &str) -> Result<(), String> {
+  query!(
+    r#"
+      INSERT INTO everytrack_cron.paused_cronjob (job_name, paused_at)
+      VALUES ($1, NOW())
+      ON CONFLICT (job_name) DO NOTHING
+    "#,
+    job_name,
+  )
+  .execute(pg_client)
+  .await
+  .map_err(|e| format!("failed to pause cronjob {job_name} in postgresql database. {}", e))?;
+
+  Ok(())
+}
+
+#[tracing::instrument(skip(pg_client))]
+pub async fn resume_cronjob(pg_client: impl PgExecutor<'_>, job_name: &str) -> Result<(), String> {
+  query!(
+    r#"
+      DELETE FROM everytrack_cron.paused_cronjob
+      WHERE job_name = $1
+    "#,
+    job_name,
+  )
+  .execute(pg_client)
+  .await
+  .map_err(|e| format!("failed to resume cronjob {job_name} in postgresql database. {}", e))?;
+
+  Ok(())
+}
+
+#[tracing::instrument(skip(pg_client))]
+pub async fn is_cronjob_paused(pg_client: impl PgExecutor<'_>, job_name: &str) -> Result<bool, String> {
+  let is_paused = query_scalar!(
+    r#"
+      SELECT EXISTS (SELECT 1 FROM everytrack_cron.paused_cronjob WHERE job_name = $1)
+    "#,
+    job_name,
+  )
+  .fetch_one(pg_client)
+  .await
+  .map_err(|e| {
+    format!(
+      "failed to check whether cronjob {job_name} is paused from postgresql database. {}",
+      e
+    )
+  });
+
+  is_paused.and_then(|r| r.ok_or("unexpected error occured when checking whether cronjob is paused".to_string()))
+}
+
+#[tracing::instrument(skip(pg_client))]
+pub async fn get_paused_cronjob_names(pg_client: impl PgExecutor<'_>) -> Result<Vec<String>, String> {
+  query_scalar!(
+    r#"
+      SELECT job_name
+      FROM everytrack_cron.paused_cronjob
+    "#,
+  )
+  .fetch_all(pg_client)
+  .await
+  .map_err(|e| format!("failed to get paused cronjobs from postgresql database. {}", e))
+}
@@ -1,4 +1,4 @@
-use sqlx::{query, query_as, Pool, Postgres};
+use sqlx::{query, query_as, PgExecutor};
 use uuid::Uuid;
 
 #[derive(Debug)]
@@ -17,8 +17,8 @@ pub struct UpdateStockCurrentPriceParams {
   pub current_price: String,
 }
 
-#[tracing::instrument]
-pub async fn get_all_stocks_by_country_id(pg_client: &Pool<Postgres>, country_id: &str) -> Result<Vec<Stock>, String> {
+#[tracing::instrument(skip(pg_client))]
+pub async fn get_all_stocks_by_country_id(pg_client: impl PgExecutor<'_>, country_id: &str) -> Result<Vec<Stock>, String> {
   query_as!(
     Stock,
     r#"
@@ -35,8 +35,8 @@ pub async fn get_all_stocks_by_country_id(pg_client: &Pool<Postgres>, country_id
   .map_err(|e| format!("failed to get all stocks by country id from postgresql database. {}", e))
 }
 
-#[tracing::instrument]
-pub async fn update_stock_current_price(pg_client: &Pool<Postgres>, params: UpdateStockCurrentPriceParams) -> Result<(), String> {
+#[tracing::instrument(skip(pg_client))]
+pub async fn update_stock_current_price(pg_client: impl PgExecutor<'_>, params: UpdateStockCurrentPriceParams) -> Result<(), String> {
   let rows_affected = query!(
     r#"
       UPDATE everytrack_backend.stock
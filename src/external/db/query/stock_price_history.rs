@@ -0,0 +1,84 @@
+use rust_decimal::Decimal;
+use sqlx::{query, query_scalar, PgExecutor};
+use time::Date;
+use uuid::Uuid;
+
+#[derive(Debug)]
+pub struct StockPriceBar {
+  pub date: Date,
+  pub open: Decimal,
+  pub high: Decimal,
+  pub low: Decimal,
+  pub close: Decimal,
+  pub adjusted_close: Decimal,
+  pub volume: i64,
+}
+
+#[derive(Debug)]
+pub struct UpsertStockPriceHistoryParams {
+  pub stock_id: Uuid,
+  pub bars: Vec<StockPriceBar>,
+}
+
+#[tracing::instrument(skip(pg_client))]
+pub async fn get_latest_stock_price_history_date(pg_client: impl PgExecutor<'_>, stock_id: Uuid) -> Result<Option<Date>, String> {
+  query_scalar!(
+    r#"
+      SELECT MAX(date) FROM everytrack_cron.stock_price_history
+      WHERE stock_id = $1
+    "#,
+    stock_id,
+  )
+  .fetch_one(pg_client)
+  .await
+  .map_err(|e| format!("failed to get latest stock price history date from postgresql database. {}", e))
+}
+
+// Insert daily bars of a stock in one statement, overwriting existing bars of the same dates
+#[tracing::instrument(skip(pg_client, params), fields(stock_id = %params.stock_id, bars = params.bars.len()))]
+pub async fn upsert_stock_price_history(pg_client: impl PgExecutor<'_>, params: UpsertStockPriceHistoryParams) -> Result<u64, String> {
+  let mut dates = vec![];
+  let mut opens = vec![];
+  let mut highs = vec![];
+  let mut lows = vec![];
+  let mut closes = vec![];
+  let mut adjusted_closes = vec![];
+  let mut volumes = vec![];
+  for bar in params.bars.into_iter() {
+    dates.push(bar.date);
+    opens.push(bar.open);
+    highs.push(bar.high);
+    lows.push(bar.low);
+    closes.push(bar.close);
+    adjusted_closes.push(bar.adjusted_close);
+    volumes.push(bar.volume);
+  }
+
+  let rows_affected = query!(
+    r#"
+      INSERT INTO everytrack_cron.stock_price_history (stock_id, date, open, high, low, close, adjusted_close, volume, updated_at)
+      SELECT $1, *, NOW() FROM UNNEST($2::DATE[], $3::NUMERIC[], $4::NUMERIC[], $5::NUMERIC[], $6::NUMERIC[], $7::NUMERIC[], $8::BIGINT[])
+      ON CONFLICT (stock_id, date) DO UPDATE
+      SET open = EXCLUDED.open, high = EXCLUDED.high, low = EXCLUDED.low, close = EXCLUDED.close,
+      adjusted_close = EXCLUDED.adjusted_close, volume = EXCLUDED.volume, updated_at = EXCLUDED.updated_at
+    "#,
+    params.stock_id,
+    &dates,
+    &opens,
+    &highs,
+    &lows,
+    &closes,
+    &adjusted_closes,
+    &volumes,
+  )
+  .execute(pg_client)
+  .await
+  .map_err(|e| format!("failed to upsert stock price history into postgresql database. {}", e))?
+  .rows_affected();
+
+  if rows_affected.ge(&0) {
+    Ok(rows_affected)
+  } else {
+    Err("unexpected error occured when upserting stock price history into postgresql database".to_string())
+  }
+}
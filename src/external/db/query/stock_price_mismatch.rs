@@ -0,0 +1,59 @@
+use sqlx::{query, PgExecutor};
+use uuid::Uuid;
+
+#[derive(Debug)]
+pub struct FlagStockPriceMismatchParams {
+  pub stock_id: Uuid,
+  pub ticker: String,
+  pub quote_currency: String,
+  pub stock_currency: String,
+  pub quote_price: String,
+}
+
+#[tracing::instrument(skip(pg_client))]
+pub async fn flag_stock_price_mismatch(pg_client: impl PgExecutor<'_>, params: FlagStockPriceMismatchParams) -> Result<(), String> {
+  let rows_affected = query!(
+    r#"
+      INSERT INTO everytrack_cron.stock_price_mismatch (stock_id, ticker, quote_currency, stock_currency, quote_price, detected_at)
+      VALUES ($1, $2, $3, $4, $5, NOW())
+      ON CONFLICT (stock_id) DO UPDATE
+      SET ticker = EXCLUDED.ticker, quote_currency = EXCLUDED.quote_currency, stock_currency = EXCLUDED.stock_currency, quote_price = EXCLUDED.quote_price, detected_at = EXCLUDED.detected_at
+    "#,
+    params.stock_id,
+    params.ticker,
+    params.quote_currency,
+    params.stock_currency,
+    params.quote_price,
+  )
+  .execute(pg_client)
+  .await
+  .map_err(|e| format!("failed to flag stock price mismatch in postgresql database. {}", e))?
+  .rows_affected();
+
+  if rows_affected.ge(&0) {
+    Ok(())
+  } else {
+    Err("unexpected error occured when flagging stock price mismatch in postgresql database".to_string())
+  }
+}
+
+#[tracing::instrument(skip(pg_client))]
+pub async fn delete_stock_price_mismatch(pg_client: impl PgExecutor<'_>, stock_id: Uuid) -> Result<(), String> {
+  let rows_affected = query!(
+    r#"
+      DELETE FROM everytrack_cron.stock_price_mismatch
+      WHERE stock_id = $1
+    "#,
+    stock_id,
+  )
+  .execute(pg_client)
+  .await
+  .map_err(|e| format!("failed to delete stock price mismatch in postgresql database. {}", e))?
+  .rows_affected();
+
+  if rows_affected.ge(&0) {
+    Ok(())
+  } else {
+    Err("unexpected error occured when deleting stock price mismatch in postgresql database".to_string())
+  }
+}
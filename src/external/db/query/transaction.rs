@@ -1,9 +1,10 @@
-use sqlx::{query, Pool, Postgres};
+use sqlx::{query, PgExecutor};
 use time::OffsetDateTime;
 use uuid::Uuid;
 
 #[derive(Debug)]
 pub struct CreateNewTransactionParams {
+  pub id: Uuid,
   pub name: String,
   pub income: bool,
   pub amount: String,
@@ -15,13 +16,14 @@ pub struct CreateNewTransactionParams {
   pub executed_at: OffsetDateTime,
 }
 
-#[tracing::instrument]
-pub async fn create_new_transaction(pg_client: &Pool<Postgres>, params: CreateNewTransactionParams) -> Result<(), String> {
+#[tracing::instrument(skip(pg_client))]
+pub async fn create_new_transaction(pg_client: impl PgExecutor<'_>, params: CreateNewTransactionParams) -> Result<(), String> {
   let rows_affected = query!(
     r#"
-      INSERT INTO everytrack_backend.transaction (client_id, account_id, currency_id, name, category, amount, income, remarks, executed_at)
-      VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+      INSERT INTO everytrack_backend.transaction (id, client_id, account_id, currency_id, name, category, amount, income, remarks, executed_at)
+      VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
     "#,
+    params.id,
     params.client_id,
     params.account_id,
     params.currency_id,
@@ -0,0 +1,73 @@
+mod ecb;
+mod fawazahmed0;
+
+use async_trait::async_trait;
+use dotenvy::var;
+use ecb::EcbProvider;
+use fawazahmed0::Fawazahmed0Provider;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use time::Date;
+use tracing::info;
+
+// Date of exchange rates to get from provider
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExchangeRateDate {
+  Latest,
+  Historical(Date),
+}
+
+impl fmt::Display for ExchangeRateDate {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ExchangeRateDate::Latest => write!(f, "latest"),
+      ExchangeRateDate::Historical(date) => write!(f, "{date}"),
+    }
+  }
+}
+
+#[async_trait]
+pub trait ExchangeRateProvider: fmt::Debug + Send + Sync {
+  // Name recorded along with the exchange rates served by the provider
+  fn name(&self) -> &'static str;
+
+  // Get exchange rates from base currency to each of the target currencies, keyed by lowercase currency ticker
+  // Target currencies without rate are left out so that the next provider can be tried for them
+  async fn get_exchange_rates(
+    &self,
+    base_currency: &str,
+    target_currencies: &[String],
+    date: ExchangeRateDate,
+  ) -> Result<HashMap<String, f64>, String>;
+}
+
+pub type ExchangeRateProviders = Arc<Vec<Box<dyn ExchangeRateProvider>>>;
+
+// Initialize exchange rate providers in the priority order defined by environment variable 'EXCHANGE_RATE_PROVIDERS'
+#[tracing::instrument]
+pub fn init_exchange_rate_providers() -> Result<ExchangeRateProviders, String> {
+  let provider_names = var("EXCHANGE_RATE_PROVIDERS").unwrap_or_else(|_| "fawazahmed0,ecb".to_string());
+  let mut providers: Vec<Box<dyn ExchangeRateProvider>> = vec![];
+  for provider_name in provider_names.split(',').map(|name| name.trim()) {
+    let provider: Box<dyn ExchangeRateProvider> = match provider_name {
+      "fawazahmed0" => Box::new(Fawazahmed0Provider::new()?),
+      "ecb" => Box::new(EcbProvider::new()?),
+      _ => {
+        return Err(format!(
+          "unknown exchange rate provider {provider_name} in environment variable EXCHANGE_RATE_PROVIDERS. expected fawazahmed0 or ecb"
+        ))
+      }
+    };
+    providers.push(provider);
+  }
+  if providers.is_empty() {
+    return Err("no exchange rate provider is configured in environment variable EXCHANGE_RATE_PROVIDERS".to_string());
+  }
+  info!(
+    "initialized exchange rate providers {}",
+    providers.iter().map(|p| p.name()).collect::<Vec<&str>>().join(", ")
+  );
+
+  Ok(Arc::new(providers))
+}
@@ -0,0 +1,253 @@
+use super::{ExchangeRateDate, ExchangeRateProvider};
+use async_trait::async_trait;
+use dotenvy::var;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Instant;
+use time::{Duration, OffsetDateTime};
+use tokio::sync::Mutex;
+use tracing::debug;
+
+// How long a downloaded feed is reused, so that one run deriving rates of every base currency
+// or a backfill going through many dates downloads each feed only once
+const FEED_CACHE_TTL: std::time::Duration = std::time::Duration::from_secs(600);
+
+const DEFAULT_ECB_EXCHANGE_RATES_API_URL: &str = "https://www.ecb.europa.eu/stats/eurofxref";
+
+// Euro foreign exchange reference rates published by European Central Bank on every working day
+// https://www.ecb.europa.eu/stats/policy_and_exchange_rates/euro_reference_exchange_rates/html/index.en.html
+pub struct EcbProvider {
+  api_url: String,
+  feed_cache: Mutex<HashMap<&'static str, EcbFeedCache>>,
+}
+
+// Reference rates against euro of every day in the feed keyed by date in format YYYY-MM-DD,
+// each of them keyed by lowercase currency ticker
+type EcbReferenceRates = BTreeMap<String, HashMap<String, f64>>;
+
+struct EcbFeedCache {
+  fetched_at: Instant,
+  reference_rates: Arc<EcbReferenceRates>,
+}
+
+impl fmt::Debug for EcbProvider {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("EcbProvider").field("api_url", &self.api_url).finish()
+  }
+}
+
+impl EcbProvider {
+  pub fn new() -> Result<Self, String> {
+    // Get value for environment variable 'ECB_EXCHANGE_RATES_API_URL', which overrides the public feeds of European Central Bank
+    let api_url = var("ECB_EXCHANGE_RATES_API_URL")
+      .ok()
+      .filter(|u| !u.is_empty())
+      .unwrap_or_else(|| DEFAULT_ECB_EXCHANGE_RATES_API_URL.to_string());
+    Ok(EcbProvider {
+      api_url,
+      feed_cache: Mutex::new(HashMap::new()),
+    })
+  }
+
+  // Download and parse the feed unless it has been done recently
+  // The cache is locked during the download so that concurrent callers wait for it instead of downloading the feed again
+  async fn get_reference_rates(&self, feed: &'static str) -> Result<Arc<EcbReferenceRates>, String> {
+    let mut feed_cache = self.feed_cache.lock().await;
+    feed_cache.retain(|_, cache| cache.fetched_at.elapsed() < FEED_CACHE_TTL);
+    if let Some(cache) = feed_cache.get(feed) {
+      return Ok(cache.reference_rates.clone());
+    }
+
+    debug!("going to download ecb reference rates from {feed}");
+    let response = reqwest::get(format!("{}/{feed}", self.api_url))
+      .await
+      .and_then(|r| r.error_for_status())
+      .map_err(|e| format!("failed to fetch ecb reference rates from {feed}. {}", e))?;
+    let body = response
+      .text()
+      .await
+      .map_err(|e| format!("failed to fetch ecb reference rates from {feed}. {}", e))?;
+    let reference_rates = Arc::new(parse_reference_rates(&body));
+    feed_cache.insert(
+      feed,
+      EcbFeedCache {
+        fetched_at: Instant::now(),
+        reference_rates: reference_rates.clone(),
+      },
+    );
+
+    Ok(reference_rates)
+  }
+}
+
+#[async_trait]
+impl ExchangeRateProvider for EcbProvider {
+  fn name(&self) -> &'static str {
+    "ecb"
+  }
+
+  #[tracing::instrument]
+  async fn get_exchange_rates(
+    &self,
+    base_currency: &str,
+    target_currencies: &[String],
+    date: ExchangeRateDate,
+  ) -> Result<HashMap<String, f64>, String> {
+    // Only the history feeds contain past rates, prefer the smaller one covering the last 90 days
+    let feed = match date {
+      ExchangeRateDate::Latest => "eurofxref-daily.xml",
+      ExchangeRateDate::Historical(date) if date >= OffsetDateTime::now_utc().date() - Duration::days(89) => "eurofxref-hist-90d.xml",
+      ExchangeRateDate::Historical(_) => "eurofxref-hist.xml",
+    };
+    let reference_rates = self.get_reference_rates(feed).await?;
+    let (reference_date, rates) =
+      get_reference_rates_of_date(&reference_rates, date).ok_or_else(|| format!("ecb reference rates do not exist on or before {date}"))?;
+
+    derive_exchange_rates(rates, base_currency, target_currencies)
+      .ok_or_else(|| format!("ecb reference rate does not exist for currency {base_currency} on {reference_date}"))
+  }
+}
+
+// Rates are not published on weekends and holidays, fall back to the latest working day on or before the date
+fn get_reference_rates_of_date(reference_rates: &EcbReferenceRates, date: ExchangeRateDate) -> Option<(&String, &HashMap<String, f64>)> {
+  match date {
+    ExchangeRateDate::Latest => reference_rates.iter().next_back(),
+    ExchangeRateDate::Historical(date) => reference_rates.range(..=date.to_string()).next_back(),
+  }
+}
+
+// Derive cross rates from the rates against euro, returns none if the base currency does not have rate
+// Target currencies without rate are left out
+fn derive_exchange_rates(rates: &HashMap<String, f64>, base_currency: &str, target_currencies: &[String]) -> Option<HashMap<String, f64>> {
+  let base_currency_rate = rates.get(base_currency)?;
+  Some(
+    target_currencies
+      .iter()
+      .filter_map(|target_currency| {
+        let target_currency_rate = rates.get(target_currency)?;
+        Some((target_currency.clone(), target_currency_rate / base_currency_rate))
+      })
+      .collect(),
+  )
+}
+
+// Extract reference rates from xml in the form of
+// <Cube time="2024-03-28"><Cube currency="USD" rate="1.0811"/>...</Cube>
+fn parse_reference_rates(body: &str) -> EcbReferenceRates {
+  let mut reference_rates = EcbReferenceRates::new();
+  let mut current_date: Option<String> = None;
+  for cube in body.split("<Cube").skip(1) {
+    let cube = &cube[..cube.find('>').unwrap_or(cube.len())];
+    if let Some(date) = get_attribute(cube, "time") {
+      // Euro is the base of all reference rates
+      reference_rates.insert(date.to_string(), HashMap::from([("eur".to_string(), 1.0)]));
+      current_date = Some(date.to_string());
+    } else if let (Some(currency), Some(rate), Some(current_rates)) = (
+      get_attribute(cube, "currency"),
+      get_attribute(cube, "rate"),
+      current_date.as_ref().and_then(|date| reference_rates.get_mut(date)),
+    ) {
+      if let Ok(rate) = rate.parse::<f64>() {
+        current_rates.insert(currency.to_lowercase(), rate);
+      }
+    }
+  }
+
+  reference_rates
+}
+
+fn get_attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+  ['\'', '"'].iter().find_map(|quote| {
+    let start = tag.find(&format!("{name}={quote}"))? + name.len() + 2;
+    let end = tag[start..].find(*quote)? + start;
+    Some(&tag[start..end])
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use time::{Date, Month};
+
+  const DAILY_FEED: &str = include_str!("../../../tests/fixtures/ecb/eurofxref-daily.xml");
+  const HIST_FEED: &str = include_str!("../../../tests/fixtures/ecb/eurofxref-hist.xml");
+
+  fn historical(year: i32, month: u8, day: u8) -> ExchangeRateDate {
+    ExchangeRateDate::Historical(Date::from_calendar_date(year, Month::try_from(month).unwrap(), day).unwrap())
+  }
+
+  fn currencies(tickers: &[&str]) -> Vec<String> {
+    tickers.iter().map(|t| t.to_string()).collect()
+  }
+
+  #[test]
+  fn parses_daily_feed() {
+    let reference_rates = parse_reference_rates(DAILY_FEED);
+    assert_eq!(reference_rates.keys().collect::<Vec<_>>(), ["2024-03-28"]);
+
+    let rates = &reference_rates["2024-03-28"];
+    // 30 published currencies and euro itself
+    assert_eq!(rates.len(), 31);
+    assert_eq!(rates["eur"], 1.0);
+    assert_eq!(rates["usd"], 1.0811);
+    assert_eq!(rates["gbp"], 0.8551);
+    assert_eq!(rates["idr"], 17157.3);
+    assert!(!rates.contains_key("USD"));
+  }
+
+  #[test]
+  fn parses_hist_feed() {
+    let reference_rates = parse_reference_rates(HIST_FEED);
+    assert_eq!(
+      reference_rates.keys().collect::<Vec<_>>(),
+      ["1999-01-04", "2024-03-27", "2024-03-28"]
+    );
+    assert_eq!(reference_rates["2024-03-28"]["usd"], 1.0811);
+    assert_eq!(reference_rates["2024-03-27"]["usd"], 1.0824);
+    assert_eq!(reference_rates["1999-01-04"]["usd"], 1.1789);
+    assert_eq!(reference_rates["1999-01-04"]["cyp"], 0.58231);
+    assert!(!reference_rates["2024-03-28"].contains_key("cyp"));
+  }
+
+  #[test]
+  fn takes_latest_working_day_on_or_before_date() {
+    let reference_rates = parse_reference_rates(HIST_FEED);
+    let reference_date = |date| get_reference_rates_of_date(&reference_rates, date).map(|(date, _)| date.as_str());
+
+    assert_eq!(reference_date(ExchangeRateDate::Latest), Some("2024-03-28"));
+    assert_eq!(reference_date(historical(2024, 3, 27)), Some("2024-03-27"));
+    // Good Friday is a holiday and the following days are weekend
+    assert_eq!(reference_date(historical(2024, 3, 29)), Some("2024-03-28"));
+    assert_eq!(reference_date(historical(2024, 3, 31)), Some("2024-03-28"));
+    assert_eq!(reference_date(historical(2024, 3, 26)), Some("1999-01-04"));
+    assert_eq!(reference_date(historical(1998, 12, 31)), None);
+  }
+
+  #[test]
+  fn derives_cross_rates_from_rates_against_euro() {
+    let reference_rates = parse_reference_rates(DAILY_FEED);
+    let rates = &reference_rates["2024-03-28"];
+
+    let exchange_rates = derive_exchange_rates(rates, "eur", &currencies(&["usd", "jpy"])).unwrap();
+    assert_eq!(
+      exchange_rates,
+      HashMap::from([("usd".to_string(), 1.0811), ("jpy".to_string(), 163.45)])
+    );
+
+    let exchange_rates = derive_exchange_rates(rates, "usd", &currencies(&["eur", "gbp", "xyz"])).unwrap();
+    assert_eq!(exchange_rates.len(), 2);
+    assert!((exchange_rates["eur"] - 1.0 / 1.0811).abs() < 1e-12);
+    assert!((exchange_rates["gbp"] - 0.8551 / 1.0811).abs() < 1e-12);
+
+    assert_eq!(derive_exchange_rates(rates, "xyz", &currencies(&["usd"])), None);
+  }
+
+  #[test]
+  fn gets_attribute_in_either_quote() {
+    assert_eq!(get_attribute(" currency='USD' rate='1.0811'/", "rate"), Some("1.0811"));
+    assert_eq!(get_attribute(" currency=\"USD\" rate=\"1.0811\"/", "currency"), Some("USD"));
+    assert_eq!(get_attribute(" time='2024-03-28'", "time"), Some("2024-03-28"));
+    assert_eq!(get_attribute(" currency='USD'", "rate"), None);
+  }
+}
@@ -0,0 +1,60 @@
+use super::{ExchangeRateDate, ExchangeRateProvider};
+use async_trait::async_trait;
+use dotenvy::var;
+use serde_json::Value;
+use std::collections::HashMap;
+
+// https://github.com/fawazahmed0/exchange-api served through jsdelivr cdn
+#[derive(Debug)]
+pub struct Fawazahmed0Provider {
+  api_url: String,
+}
+
+impl Fawazahmed0Provider {
+  pub fn new() -> Result<Self, String> {
+    // Get value for environment variable 'EXCHANGE_RATES_API_URL'
+    let api_url =
+      var("EXCHANGE_RATES_API_URL").map_err(|e| format!("Missing config for environment variable EXCHANGE_RATES_API_URL. {}", e))?;
+    Ok(Fawazahmed0Provider { api_url })
+  }
+}
+
+#[async_trait]
+impl ExchangeRateProvider for Fawazahmed0Provider {
+  fn name(&self) -> &'static str {
+    "fawazahmed0"
+  }
+
+  #[tracing::instrument]
+  async fn get_exchange_rates(
+    &self,
+    base_currency: &str,
+    target_currencies: &[String],
+    date: ExchangeRateDate,
+  ) -> Result<HashMap<String, f64>, String> {
+    let response = reqwest::get(format!("{}@{date}/v1/currencies/{base_currency}.json", self.api_url))
+      .await
+      .and_then(|r| r.error_for_status())
+      .map_err(|e| format!("failed to fetch exchange rates with base currency {base_currency}. {}", e))?;
+
+    // Convert raw API response to consumable exchange rates json for processing
+    let exchange_rate_data = response
+      .json::<HashMap<String, Value>>()
+      .await
+      .map_err(|e| format!("failed to fetch exchange rates with base currency {base_currency}. {}", e))?;
+
+    // Extract exchange rates pair based on target source currency
+    let exchange_rate_list = exchange_rate_data
+      .get(base_currency)
+      .and_then(|list| list.as_object())
+      .ok_or_else(|| format!("exchange rate list does not exist for base currency {base_currency}"))?;
+    let mut exchange_rates = HashMap::new();
+    for target_currency in target_currencies.iter() {
+      if let Some(exchange_rate_value) = exchange_rate_list.get(target_currency).and_then(|value| value.as_f64()) {
+        exchange_rates.insert(target_currency.clone(), exchange_rate_value);
+      }
+    }
+
+    Ok(exchange_rates)
+  }
+}
@@ -0,0 +1,87 @@
+use dotenvy::var;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use time::OffsetDateTime;
+use tokio::sync::{Mutex, Semaphore, SemaphorePermit};
+use tokio::time::{sleep_until, Instant};
+use tracing::info;
+use yahoo_finance_api::{YResponse, YahooConnector, YahooError};
+
+// Yahoo finance api client shared by all cronjobs, which bounds the number of concurrent requests
+// and spaces requests out to the configured rate so that yahoo finance does not throttle us
+pub struct YahooFinanceClient {
+  connector: YahooConnector,
+  concurrency: Semaphore,
+  request_interval: Duration,
+  next_request_at: Mutex<Instant>,
+}
+
+impl fmt::Debug for YahooFinanceClient {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("YahooFinanceClient")
+      .field("available_permits", &self.concurrency.available_permits())
+      .field("request_interval", &self.request_interval)
+      .finish()
+  }
+}
+
+impl YahooFinanceClient {
+  // Wait for a free slot and the next request time, the returned permit should be held until the request completes
+  async fn throttle(&self) -> SemaphorePermit<'_> {
+    let permit = self
+      .concurrency
+      .acquire()
+      .await
+      .expect("yahoo finance client semaphore should never be closed");
+    let request_at = {
+      let mut next_request_at = self.next_request_at.lock().await;
+      let request_at = (*next_request_at).max(Instant::now());
+      *next_request_at = request_at + self.request_interval;
+      request_at
+    };
+    sleep_until(request_at).await;
+    permit
+  }
+
+  pub async fn get_latest_quotes(&self, ticker: &str, interval: &str) -> Result<YResponse, YahooError> {
+    let _permit = self.throttle().await;
+    self.connector.get_latest_quotes(ticker, interval).await
+  }
+
+  pub async fn get_quote_history(&self, ticker: &str, start: OffsetDateTime, end: OffsetDateTime) -> Result<YResponse, YahooError> {
+    let _permit = self.throttle().await;
+    self.connector.get_quote_history(ticker, start, end).await
+  }
+
+  pub async fn get_quote_range(&self, ticker: &str, interval: &str, range: &str) -> Result<YResponse, YahooError> {
+    let _permit = self.throttle().await;
+    self.connector.get_quote_range(ticker, interval, range).await
+  }
+}
+
+// Initialize yahoo finance api client with limits from environment variables
+// 'YAHOO_FINANCE_MAX_CONCURRENT_REQUESTS' and 'YAHOO_FINANCE_MAX_REQUESTS_PER_SECOND'
+#[tracing::instrument]
+pub fn init_yahoo_finance_client() -> Result<Arc<YahooFinanceClient>, String> {
+  let max_concurrent_requests = match var("YAHOO_FINANCE_MAX_CONCURRENT_REQUESTS") {
+    Ok(limit) => limit.parse::<usize>().ok().filter(|l| *l > 0).ok_or_else(|| {
+      format!("invalid value {limit} for environment variable YAHOO_FINANCE_MAX_CONCURRENT_REQUESTS. expected a positive integer")
+    })?,
+    Err(_) => 4,
+  };
+  let max_requests_per_second = match var("YAHOO_FINANCE_MAX_REQUESTS_PER_SECOND") {
+    Ok(limit) => limit.parse::<f64>().ok().filter(|l| l.is_finite() && *l > 0.0).ok_or_else(|| {
+      format!("invalid value {limit} for environment variable YAHOO_FINANCE_MAX_REQUESTS_PER_SECOND. expected a positive number")
+    })?,
+    Err(_) => 2.0,
+  };
+  info!("initialized yahoo finance api client with at most {max_concurrent_requests} concurrent requests and {max_requests_per_second} requests per second");
+
+  Ok(Arc::new(YahooFinanceClient {
+    connector: YahooConnector::new(),
+    concurrency: Semaphore::new(max_concurrent_requests),
+    request_interval: Duration::from_secs_f64(1.0 / max_requests_per_second),
+    next_request_at: Mutex::new(Instant::now()),
+  }))
+}
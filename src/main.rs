@@ -6,6 +6,12 @@ mod server;
 mod utils;
 
 use dotenvy::var;
+use std::time::Duration;
+use tokio::time::timeout;
+use tracing::{info, warn};
+
+// Time given to each database client to close its connections on shutdown
+const DATABASE_CLOSE_TIMEOUT: Duration = Duration::from_secs(3);
 
 #[tokio::main]
 async fn main() {
@@ -16,10 +22,41 @@ async fn main() {
   }
   // Initialize logger
   logger::init();
-  // Create a new database client
+  // Refuse to start without a proper admin api key before connecting to anything
+  let admin_api_key = config::load_admin_api_key().unwrap_or_else(|e| panic!("{}", e));
+  // Create new database clients
   let pg_client = external::db::client::init_pg().await.unwrap_or_else(|e| panic!("{}", e));
+  // Apply database migrations for tables owned by cron service
+  external::db::client::migrate_pg(&pg_client)
+    .await
+    .unwrap_or_else(|e| panic!("{}", e));
+  let mdb_client = external::db::client::init_mdb().await.unwrap_or_else(|e| panic!("{}", e));
+  let shutdown_grace_period = config::load_shutdown_grace_period().unwrap_or_else(|e| panic!("{}", e));
   // Setup cronjobs
-  cron::init().await;
-  // Initialize web server
-  server::init(pg_client).await;
+  let context = cron::JobContext {
+    pg_client: pg_client.clone(),
+    mdb_client: mdb_client.clone(),
+    exchange_rate_providers: external::exchange_rate_provider::init_exchange_rate_providers().unwrap_or_else(|e| panic!("{}", e)),
+    yahoo_finance_client: external::yahoo_finance::init_yahoo_finance_client().unwrap_or_else(|e| panic!("{}", e)),
+  };
+  let cronjobs = cron::init(context.clone()).await;
+  // Initialize web server, which returns once termination signal is received
+  server::init(context, cronjobs.clone(), admin_api_key).await;
+
+  // Let running cronjobs finish before closing the database clients
+  cronjobs.shutdown(shutdown_grace_period).await;
+  // Closing waits for the connections in use to be returned, which should not hold up the exit if any of them is stuck
+  if timeout(DATABASE_CLOSE_TIMEOUT, pg_client.close()).await.is_err() {
+    warn!(
+      "postgresql connections were not returned within {}s. exiting anyway",
+      DATABASE_CLOSE_TIMEOUT.as_secs()
+    );
+  }
+  if timeout(DATABASE_CLOSE_TIMEOUT, mdb_client.shutdown()).await.is_err() {
+    warn!(
+      "mongodb client did not shut down within {}s. exiting anyway",
+      DATABASE_CLOSE_TIMEOUT.as_secs()
+    );
+  }
+  info!("database clients closed. bye");
 }
@@ -1,34 +1,64 @@
 mod handlers;
+mod middleware;
 
-use axum::routing::get;
+use crate::cron::{CronjobRegistry, JobContext};
+use crate::utils;
+use axum::routing::{get, post};
 use axum::Router;
 use dotenvy::var;
-use handlers::health_check_handler;
-use sqlx::{Pool, Postgres};
+use handlers::{
+  approve_exchange_rate_quarantine_handler, create_exchange_rate_backfill_handler, get_exchange_rate_backfill_handler,
+  health_check_handler, list_cronjobs_handler, list_exchange_rate_quarantines_handler, pause_cronjob_handler, resume_cronjob_handler,
+  resume_exchange_rate_backfill_handler, trigger_cronjob_handler,
+};
 use std::net::SocketAddr;
-// use std::sync::Arc;
+use std::sync::Arc;
 use tokio::net::TcpListener;
 use tower::ServiceBuilder;
 use tower_http::trace::TraceLayer;
 use tracing::info;
 
-// #[derive(Debug)]
-// pub struct ServerState {
-//   db: Pool<Postgres>,
-// }
+pub struct ServerState {
+  pub context: JobContext,
+  pub cronjobs: CronjobRegistry,
+  pub admin_api_key: String,
+}
 
-// Initialize an axum web server instance
-#[tracing::instrument]
-pub async fn init(pg_client: Pool<Postgres>) {
-  // let server_state = Arc::new(ServerState { db: db_client });
+// Initialize an axum web server instance, which stops accepting requests on termination signal
+#[tracing::instrument(skip(context, cronjobs, admin_api_key))]
+pub async fn init(context: JobContext, cronjobs: CronjobRegistry, admin_api_key: String) {
+  let server_state = Arc::new(ServerState {
+    cronjobs,
+    context,
+    admin_api_key,
+  });
   // https://stackoverflow.com/questions/74302133/how-to-log-and-filter-requests-with-axum-tokio
   let service = ServiceBuilder::new().layer(TraceLayer::new_for_http());
+  // Define the admin routes which are only accessible with admin api key
+  let admin_routes = Router::new()
+    .route("/jobs", get(list_cronjobs_handler))
+    .route("/jobs/:name/trigger", post(trigger_cronjob_handler))
+    .route("/jobs/:name/pause", post(pause_cronjob_handler))
+    .route("/jobs/:name/resume", post(resume_cronjob_handler))
+    .route("/exchange-rates/backfills", post(create_exchange_rate_backfill_handler))
+    .route("/exchange-rates/backfills/:id", get(get_exchange_rate_backfill_handler))
+    .route("/exchange-rates/backfills/:id/resume", post(resume_exchange_rate_backfill_handler))
+    .route("/exchange-rates/quarantine", get(list_exchange_rate_quarantines_handler))
+    .route(
+      "/exchange-rates/quarantine/:id/approve",
+      post(approve_exchange_rate_quarantine_handler),
+    )
+    .route_layer(axum::middleware::from_fn_with_state(
+      server_state.clone(),
+      middleware::require_admin_api_key,
+    ));
+  let api_version_one_routes = Router::new().nest("/admin", admin_routes);
   // Define the routes for web server
   let app = Router::new()
     .route("/", get(health_check_handler))
-    // .nest("/api/v1", api_version_one_routes)
+    .nest("/api/v1", api_version_one_routes)
     .layer(service)
-    // .with_state(server_state)
+    .with_state(server_state)
     .into_make_service();
 
   // Try to get the environment variables 'WEB_SERVER_HOST' and 'WEB_SERVER_PORT' that define the public exposure details of web server
@@ -43,6 +73,8 @@ pub async fn init(pg_client: Pool<Postgres>) {
       .unwrap(),
     app,
   )
+  .with_graceful_shutdown(utils::shutdown_signal())
   .await
-  .unwrap()
+  .unwrap();
+  info!("web server stopped");
 }
@@ -1,8 +1,26 @@
+use super::ServerState;
+use crate::cron::anomaly::approve_quarantined_exchange_rate;
+use crate::cron::backfill::{
+  acquire_exchange_rate_backfill_lock, create_exchange_rate_backfill, get_latest_backfillable_date, run_exchange_rate_backfill,
+};
+use crate::external::db::query::exchange_rate_backfill::{
+  get_exchange_rate_backfill_by_id, update_exchange_rate_backfill_status, UpdateExchangeRateBackfillStatusParams,
+};
+use crate::external::db::query::exchange_rate_quarantine::{
+  get_exchange_rate_quarantine_by_id, get_pending_exchange_rate_quarantines, ExchangeRateQuarantine,
+};
+use crate::external::db::query::job_run::get_latest_job_runs;
+use crate::external::db::query::paused_cronjob::get_paused_cronjob_names;
+use crate::utils::format_timestamp;
+use axum::extract::{Path, State};
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
 use axum::Json;
-use serde::Serialize;
-use tracing::info;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use time::{format_description, Date};
+use tracing::{error, info};
+use uuid::Uuid;
 
 #[derive(Debug, Serialize)]
 pub struct BaseResponse {
@@ -21,9 +39,454 @@ pub struct SuccessResponse<T> {
   pub result: T,
 }
 
+#[derive(Debug, Serialize)]
+pub struct LastJobRun {
+  pub id: String,
+  pub status: String,
+  pub error: Option<String>,
+  pub items_processed: Option<i64>,
+  pub failed_items: Vec<String>,
+  pub skipped_items: Vec<String>,
+  pub inserted_items: Option<i64>,
+  pub updated_items: Option<i64>,
+  pub unchanged_items: Option<i64>,
+  pub attempts: i32,
+  pub duration_ms: Option<i64>,
+  pub scheduled_at: String,
+  pub started_at: String,
+  pub finished_at: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CronjobDetails {
+  pub name: String,
+  pub schedule: String,
+  pub enabled: bool,
+  pub paused: bool,
+  pub timeout_seconds: Option<u64>,
+  pub overlap_policy: String,
+  pub next_tick: Option<String>,
+  pub last_run: Option<LastJobRun>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateExchangeRateBackfillRequest {
+  pub start_date: String,
+  pub end_date: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExchangeRateBackfillDetails {
+  pub id: String,
+  pub start_date: String,
+  pub end_date: String,
+  pub status: String,
+  pub error: Option<String>,
+  pub total_days: i32,
+  pub completed_days: i32,
+  pub skipped_days: i32,
+  pub last_completed_date: Option<String>,
+  pub created_at: String,
+  pub updated_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExchangeRateQuarantineDetails {
+  pub id: String,
+  pub base_currency_id: String,
+  pub target_currency_id: String,
+  pub rate: String,
+  pub previous_rate: Option<String>,
+  pub provider: String,
+  pub reason: String,
+  pub status: String,
+  pub created_at: String,
+  pub updated_at: String,
+}
+
+impl From<ExchangeRateQuarantine> for ExchangeRateQuarantineDetails {
+  fn from(quarantine: ExchangeRateQuarantine) -> Self {
+    ExchangeRateQuarantineDetails {
+      id: quarantine.id.to_string(),
+      base_currency_id: quarantine.base_currency_id.to_string(),
+      target_currency_id: quarantine.target_currency_id.to_string(),
+      rate: quarantine.rate,
+      previous_rate: quarantine.previous_rate,
+      provider: quarantine.provider,
+      reason: quarantine.reason,
+      status: quarantine.status,
+      created_at: format_timestamp(quarantine.created_at).unwrap_or_default(),
+      updated_at: format_timestamp(quarantine.updated_at).unwrap_or_default(),
+    }
+  }
+}
+
 // Handler function for path '/'
 #[tracing::instrument]
 pub async fn health_check_handler() -> impl IntoResponse {
   info!("received request");
   (StatusCode::OK, Json(BaseResponse { success: true }))
 }
+
+// Handler function for path '/api/v1/admin/jobs'
+#[tracing::instrument(skip(state))]
+pub async fn list_cronjobs_handler(State(state): State<Arc<ServerState>>) -> impl IntoResponse {
+  info!("received request");
+  let latest_job_runs = match get_latest_job_runs(&state.context.pg_client).await {
+    Ok(latest_job_runs) => latest_job_runs,
+    Err(e) => {
+      error!("{}", e);
+      return (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { success: false, error: e })).into_response();
+    }
+  };
+  let paused_cronjob_names = match get_paused_cronjob_names(&state.context.pg_client).await {
+    Ok(paused_cronjob_names) => paused_cronjob_names,
+    Err(e) => {
+      error!("{}", e);
+      return (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { success: false, error: e })).into_response();
+    }
+  };
+
+  let mut cronjobs = vec![];
+  for cronjob in state.cronjobs.list() {
+    let next_tick = state.cronjobs.next_tick(cronjob.name).await.and_then(|t| format_timestamp(t).ok());
+    let last_run = latest_job_runs.iter().find(|r| r.job_name == cronjob.name).map(|r| LastJobRun {
+      id: r.id.to_string(),
+      status: r.status.clone(),
+      error: r.error.clone(),
+      items_processed: r.items_processed,
+      failed_items: r.failed_items.clone(),
+      skipped_items: r.skipped_items.clone(),
+      inserted_items: r.inserted_items,
+      updated_items: r.updated_items,
+      unchanged_items: r.unchanged_items,
+      attempts: r.attempts,
+      duration_ms: r.duration_ms,
+      scheduled_at: format_timestamp(r.scheduled_at).unwrap_or_default(),
+      started_at: format_timestamp(r.started_at).unwrap_or_default(),
+      finished_at: r.finished_at.and_then(|t| format_timestamp(t).ok()),
+    });
+    cronjobs.push(CronjobDetails {
+      next_tick,
+      last_run,
+      paused: paused_cronjob_names.iter().any(|n| n == cronjob.name),
+      name: cronjob.name.to_string(),
+      enabled: cronjob.config.enabled,
+      schedule: cronjob.config.schedule.clone(),
+      timeout_seconds: cronjob.config.timeout.map(|t| t.as_secs()),
+      overlap_policy: cronjob.config.overlap_policy.to_string(),
+    });
+  }
+
+  (
+    StatusCode::OK,
+    Json(SuccessResponse {
+      success: true,
+      result: cronjobs,
+    }),
+  )
+    .into_response()
+}
+
+// Handler function for path '/api/v1/admin/jobs/:name/trigger'
+#[tracing::instrument(skip(state))]
+pub async fn trigger_cronjob_handler(State(state): State<Arc<ServerState>>, Path(name): Path<String>) -> impl IntoResponse {
+  info!("received request");
+  let Some(cronjob) = state.cronjobs.get(&name) else {
+    return cronjob_not_found(&name);
+  };
+  // Run the cronjob in background so that long running jobs will not block the request
+  tokio::spawn(async move { cronjob.execute(true).await });
+  info!("triggered cronjob {name} on demand");
+
+  (StatusCode::ACCEPTED, Json(BaseResponse { success: true })).into_response()
+}
+
+// Handler function for path '/api/v1/admin/jobs/:name/pause'
+#[tracing::instrument(skip(state))]
+pub async fn pause_cronjob_handler(State(state): State<Arc<ServerState>>, Path(name): Path<String>) -> impl IntoResponse {
+  info!("received request");
+  let Some(cronjob) = state.cronjobs.get(&name) else {
+    return cronjob_not_found(&name);
+  };
+  if let Err(e) = cronjob.pause().await {
+    error!("{}", e);
+    return (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { success: false, error: e })).into_response();
+  }
+  info!("paused cronjob {name}");
+
+  (StatusCode::OK, Json(BaseResponse { success: true })).into_response()
+}
+
+// Handler function for path '/api/v1/admin/jobs/:name/resume'
+#[tracing::instrument(skip(state))]
+pub async fn resume_cronjob_handler(State(state): State<Arc<ServerState>>, Path(name): Path<String>) -> impl IntoResponse {
+  info!("received request");
+  let Some(cronjob) = state.cronjobs.get(&name) else {
+    return cronjob_not_found(&name);
+  };
+  if let Err(e) = cronjob.resume().await {
+    error!("{}", e);
+    return (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { success: false, error: e })).into_response();
+  }
+  info!("resumed cronjob {name}");
+
+  (StatusCode::OK, Json(BaseResponse { success: true })).into_response()
+}
+
+// Handler function for path '/api/v1/admin/exchange-rates/backfills'
+#[tracing::instrument(skip(state))]
+pub async fn create_exchange_rate_backfill_handler(
+  State(state): State<Arc<ServerState>>,
+  Json(request): Json<CreateExchangeRateBackfillRequest>,
+) -> impl IntoResponse {
+  info!("received request");
+  let pg_client = &state.context.pg_client;
+  let date_format = format_description::parse("[year]-[month]-[day]").unwrap();
+  let (start_date, end_date) = match (
+    Date::parse(&request.start_date, &date_format),
+    Date::parse(&request.end_date, &date_format),
+  ) {
+    (Ok(start_date), Ok(end_date)) => (start_date, end_date),
+    _ => return bad_request("start_date and end_date should be in format YYYY-MM-DD".to_string()),
+  };
+  if start_date > end_date {
+    return bad_request("start_date should not be later than end_date".to_string());
+  }
+  let latest_backfillable_date = get_latest_backfillable_date();
+  if end_date > latest_backfillable_date {
+    return bad_request(format!("end_date should not be later than {latest_backfillable_date}"));
+  }
+
+  // Count the backfill as running execution so that shutdown waits for it to stop
+  let Some(execution) = state.cronjobs.start_background_execution() else {
+    return service_shutting_down();
+  };
+  let cluster_lock = match acquire_exchange_rate_backfill_lock(pg_client).await {
+    Ok(Some(cluster_lock)) => cluster_lock,
+    Ok(None) => return exchange_rate_backfill_already_running(),
+    Err(e) => return internal_server_error(e),
+  };
+  let id = match create_exchange_rate_backfill(pg_client, start_date, end_date).await {
+    Ok(id) => id,
+    Err(e) => return internal_server_error(e),
+  };
+  let backfill = match get_exchange_rate_backfill_by_id(pg_client, id).await {
+    Ok(Some(backfill)) => backfill,
+    Ok(None) => return internal_server_error(format!("exchange rate backfill {id} does not exist")),
+    Err(e) => return internal_server_error(e),
+  };
+  // Run the backfill in background as it can take hours for a long date range
+  let context = state.context.clone();
+  tokio::spawn(async move { run_exchange_rate_backfill(context, backfill, cluster_lock, execution).await });
+  info!("started exchange rate backfill {id} from {start_date} to {end_date}");
+
+  (
+    StatusCode::ACCEPTED,
+    Json(SuccessResponse {
+      success: true,
+      result: id.to_string(),
+    }),
+  )
+    .into_response()
+}
+
+// Handler function for path '/api/v1/admin/exchange-rates/backfills/:id'
+#[tracing::instrument(skip(state))]
+pub async fn get_exchange_rate_backfill_handler(State(state): State<Arc<ServerState>>, Path(id): Path<Uuid>) -> impl IntoResponse {
+  info!("received request");
+  let backfill = match get_exchange_rate_backfill_by_id(&state.context.pg_client, id).await {
+    Ok(Some(backfill)) => backfill,
+    Ok(None) => return exchange_rate_backfill_not_found(id),
+    Err(e) => return internal_server_error(e),
+  };
+
+  (
+    StatusCode::OK,
+    Json(SuccessResponse {
+      success: true,
+      result: ExchangeRateBackfillDetails {
+        id: backfill.id.to_string(),
+        start_date: backfill.start_date.to_string(),
+        end_date: backfill.end_date.to_string(),
+        status: backfill.status,
+        error: backfill.error,
+        total_days: backfill.total_days,
+        completed_days: backfill.completed_days,
+        skipped_days: backfill.skipped_days,
+        last_completed_date: backfill.last_completed_date.map(|d| d.to_string()),
+        created_at: format_timestamp(backfill.created_at).unwrap_or_default(),
+        updated_at: format_timestamp(backfill.updated_at).unwrap_or_default(),
+      },
+    }),
+  )
+    .into_response()
+}
+
+// Handler function for path '/api/v1/admin/exchange-rates/backfills/:id/resume'
+#[tracing::instrument(skip(state))]
+pub async fn resume_exchange_rate_backfill_handler(State(state): State<Arc<ServerState>>, Path(id): Path<Uuid>) -> impl IntoResponse {
+  info!("received request");
+  let pg_client = &state.context.pg_client;
+  let backfill = match get_exchange_rate_backfill_by_id(pg_client, id).await {
+    Ok(Some(backfill)) => backfill,
+    Ok(None) => return exchange_rate_backfill_not_found(id),
+    Err(e) => return internal_server_error(e),
+  };
+  if backfill.status == "succeeded" {
+    return (
+      StatusCode::CONFLICT,
+      Json(ErrorResponse {
+        success: false,
+        error: format!("exchange rate backfill {id} has already completed"),
+      }),
+    )
+      .into_response();
+  }
+
+  // Count the backfill as running execution so that shutdown waits for it to stop
+  let Some(execution) = state.cronjobs.start_background_execution() else {
+    return service_shutting_down();
+  };
+  // A backfill marked as running without holding the lock was interrupted, e.g. by a restart, and can be resumed
+  let cluster_lock = match acquire_exchange_rate_backfill_lock(pg_client).await {
+    Ok(Some(cluster_lock)) => cluster_lock,
+    Ok(None) => return exchange_rate_backfill_already_running(),
+    Err(e) => return internal_server_error(e),
+  };
+  if let Err(e) = update_exchange_rate_backfill_status(
+    pg_client,
+    UpdateExchangeRateBackfillStatusParams {
+      id,
+      error: None,
+      status: "running".to_string(),
+    },
+  )
+  .await
+  {
+    return internal_server_error(e);
+  }
+  let context = state.context.clone();
+  tokio::spawn(async move { run_exchange_rate_backfill(context, backfill, cluster_lock, execution).await });
+  info!("resumed exchange rate backfill {id}");
+
+  (StatusCode::ACCEPTED, Json(BaseResponse { success: true })).into_response()
+}
+
+// Handler function for path '/api/v1/admin/exchange-rates/quarantine'
+#[tracing::instrument(skip(state))]
+pub async fn list_exchange_rate_quarantines_handler(State(state): State<Arc<ServerState>>) -> impl IntoResponse {
+  info!("received request");
+  match get_pending_exchange_rate_quarantines(&state.context.pg_client).await {
+    Ok(quarantines) => (
+      StatusCode::OK,
+      Json(SuccessResponse {
+        success: true,
+        result: quarantines
+          .into_iter()
+          .map(ExchangeRateQuarantineDetails::from)
+          .collect::<Vec<ExchangeRateQuarantineDetails>>(),
+      }),
+    )
+      .into_response(),
+    Err(e) => internal_server_error(e),
+  }
+}
+
+// Handler function for path '/api/v1/admin/exchange-rates/quarantine/:id/approve'
+#[tracing::instrument(skip(state))]
+pub async fn approve_exchange_rate_quarantine_handler(State(state): State<Arc<ServerState>>, Path(id): Path<Uuid>) -> impl IntoResponse {
+  info!("received request");
+  let pg_client = &state.context.pg_client;
+  let quarantine = match get_exchange_rate_quarantine_by_id(pg_client, id).await {
+    Ok(Some(quarantine)) => quarantine,
+    Ok(None) => {
+      return (
+        StatusCode::NOT_FOUND,
+        Json(ErrorResponse {
+          success: false,
+          error: format!("exchange rate quarantine {id} does not exist"),
+        }),
+      )
+        .into_response()
+    }
+    Err(e) => return internal_server_error(e),
+  };
+  // Only the latest suspicious rate of the pair is pending, older ones have been replaced or superseded
+  if quarantine.status != "pending" {
+    return (
+      StatusCode::CONFLICT,
+      Json(ErrorResponse {
+        success: false,
+        error: format!("exchange rate quarantine {id} is {} instead of pending", quarantine.status),
+      }),
+    )
+      .into_response();
+  }
+  match approve_quarantined_exchange_rate(pg_client, &quarantine).await {
+    Ok(true) => (StatusCode::OK, Json(BaseResponse { success: true })).into_response(),
+    // Superseded by a newer rate passing the checks after the quarantine was read
+    Ok(false) => (
+      StatusCode::CONFLICT,
+      Json(ErrorResponse {
+        success: false,
+        error: format!("exchange rate quarantine {id} is no longer pending"),
+      }),
+    )
+      .into_response(),
+    Err(e) => internal_server_error(e),
+  }
+}
+
+fn bad_request(error: String) -> axum::response::Response {
+  (StatusCode::BAD_REQUEST, Json(ErrorResponse { success: false, error })).into_response()
+}
+
+fn internal_server_error(error: String) -> axum::response::Response {
+  error!("{}", error);
+  (StatusCode::INTERNAL_SERVER_ERROR, Json(ErrorResponse { success: false, error })).into_response()
+}
+
+fn exchange_rate_backfill_not_found(id: Uuid) -> axum::response::Response {
+  (
+    StatusCode::NOT_FOUND,
+    Json(ErrorResponse {
+      success: false,
+      error: format!("exchange rate backfill {id} does not exist"),
+    }),
+  )
+    .into_response()
+}
+
+fn exchange_rate_backfill_already_running() -> axum::response::Response {
+  (
+    StatusCode::CONFLICT,
+    Json(ErrorResponse {
+      success: false,
+      error: "another exchange rate backfill is running".to_string(),
+    }),
+  )
+    .into_response()
+}
+
+fn service_shutting_down() -> axum::response::Response {
+  (
+    StatusCode::SERVICE_UNAVAILABLE,
+    Json(ErrorResponse {
+      success: false,
+      error: "service is shutting down".to_string(),
+    }),
+  )
+    .into_response()
+}
+
+fn cronjob_not_found(name: &str) -> axum::response::Response {
+  (
+    StatusCode::NOT_FOUND,
+    Json(ErrorResponse {
+      success: false,
+      error: format!("cronjob {name} does not exist"),
+    }),
+  )
+    .into_response()
+}
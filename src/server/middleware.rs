@@ -0,0 +1,36 @@
+use super::handlers::ErrorResponse;
+use super::ServerState;
+use axum::extract::{Request, State};
+use axum::http::header::AUTHORIZATION;
+use axum::http::StatusCode;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use std::sync::Arc;
+use subtle::ConstantTimeEq;
+use tracing::warn;
+
+// Reject the request unless it carries header 'Authorization: Bearer <ADMIN_API_KEY>'
+pub async fn require_admin_api_key(State(state): State<Arc<ServerState>>, request: Request, next: Next) -> Response {
+  let api_key = request
+    .headers()
+    .get(AUTHORIZATION)
+    .and_then(|value| value.to_str().ok())
+    .and_then(|value| value.strip_prefix("Bearer "));
+
+  match api_key {
+    // Compare in constant time so that the key cannot be guessed from how long the comparison takes
+    Some(api_key) if bool::from(api_key.as_bytes().ct_eq(state.admin_api_key.as_bytes())) => next.run(request).await,
+    _ => {
+      warn!("rejected unauthorized request to admin endpoint {}", request.uri());
+      (
+        StatusCode::UNAUTHORIZED,
+        Json(ErrorResponse {
+          success: false,
+          error: "unauthorized".to_string(),
+        }),
+      )
+        .into_response()
+    }
+  }
+}
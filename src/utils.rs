@@ -1,4 +1,5 @@
 use time::{format_description, OffsetDateTime};
+use tracing::info;
 
 pub fn format_timestamp(timestamp: OffsetDateTime) -> Result<String, String> {
   timestamp
@@ -8,3 +9,24 @@ pub fn format_timestamp(timestamp: OffsetDateTime) -> Result<String, String> {
     )
     .map_err(|e| format!("failed to format timestamp. {}", e))
 }
+
+// Resolve when the process receives SIGTERM, e.g. from fly.io on deployment, or ctrl+c
+pub async fn shutdown_signal() {
+  let ctrl_c = async {
+    tokio::signal::ctrl_c().await.expect("Failed to install ctrl+c handler");
+  };
+  #[cfg(unix)]
+  let terminate = async {
+    tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+      .expect("Failed to install SIGTERM handler")
+      .recv()
+      .await;
+  };
+  #[cfg(not(unix))]
+  let terminate = std::future::pending::<()>();
+
+  tokio::select! {
+    _ = ctrl_c => info!("received ctrl+c signal. shutting down"),
+    _ = terminate => info!("received SIGTERM signal. shutting down"),
+  }
+}